@@ -0,0 +1,87 @@
+//! Compares the uniform grid broadphase with the all-pairs loop it replaced.
+//!
+//! `cargo bench --bench broadphase`
+
+#[path = "../src/spatial.rs"]
+mod spatial;
+
+use spatial::SpatialGrid;
+use std::time::{Duration, Instant};
+
+const RADIUS: f32 = 15f32;
+/// Keeps the density of the default stage: about one character per 100x100 pixels.
+const AREA_PER_ENTITY: f32 = 100f32 * 100f32;
+
+/// Small deterministic generator so every run measures the same layout.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> f32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn layout(n: usize) -> Vec<(f32, f32)> {
+    let side = (n as f32 * AREA_PER_ENTITY).sqrt();
+    let mut rng = Lcg(n as u64);
+    (0..n).map(|_| (rng.next() * side, rng.next() * side)).collect()
+}
+
+fn naive(points: &[(f32, f32)]) -> usize {
+    let mut hits = 0;
+    for (i, a) in points.iter().enumerate() {
+        for (j, b) in points.iter().enumerate() {
+            let reach = RADIUS * 2f32;
+            if i != j && (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) <= reach * reach {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+fn grid(grid: &mut SpatialGrid<usize>, points: &[(f32, f32)]) -> usize {
+    grid.clear();
+    for (i, p) in points.iter().enumerate() {
+        grid.insert(i, p.0, p.1, RADIUS);
+    }
+    let mut hits = 0;
+    for (i, p) in points.iter().enumerate() {
+        hits += grid
+            .query(p.0, p.1, RADIUS)
+            .into_iter()
+            .filter(|j| *j != i)
+            .count();
+    }
+    hits
+}
+
+fn measure<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    // one warm-up round so the grid has allocated its cells
+    let mut hits = f();
+    let rounds = 5;
+    let start = Instant::now();
+    for _ in 0..rounds {
+        hits = f();
+    }
+    (start.elapsed() / rounds, hits)
+}
+
+fn main() {
+    println!("{:>8} {:>14} {:>14} {:>8}", "entities", "all pairs", "grid", "hits");
+    for n in [100, 1_000, 5_000, 10_000].iter() {
+        let points = layout(*n);
+        let mut spatial = SpatialGrid::default();
+        let (grid_time, grid_hits) = measure(|| grid(&mut spatial, &points));
+        let (naive_time, naive_hits) = measure(|| naive(&points));
+        assert_eq!(grid_hits, naive_hits);
+        println!(
+            "{:>8} {:>14?} {:>14?} {:>8}",
+            n, naive_time, grid_time, grid_hits
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::snapshot::VectorDef;
+use quicksilver::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Distance in pixels at which a patrol point counts as reached.
+const ARRIVE_DISTANCE: f32 = 4f32;
+/// How far ahead of itself a fleeing enemy aims, which sets its running speed.
+const FLEE_DISTANCE: f32 = 60f32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum AiState {
+    Idle,
+    Patrol,
+    Chase,
+    /// Telegraphs the attack, standing still and facing the target.
+    Windup,
+    Attack,
+    Recover,
+    Flee,
+}
+
+impl Default for AiState {
+    fn default() -> Self {
+        AiState::Idle
+    }
+}
+
+/// Ranges and timings of one enemy archetype, as written in its prefab.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct AiTuning {
+    /// Distance to the target at which the windup starts.
+    pub attack_range: f32,
+    pub windup: f32,
+    pub recover: f32,
+    /// Health ratio below which the enemy runs away.
+    #[serde(default)]
+    pub flee_health: f32,
+    /// Seconds spent idle between two patrol legs.
+    #[serde(default)]
+    pub patrol_wait: f32,
+    /// Patrol points relative to the spawn position.
+    #[serde(default)]
+    pub patrol: Vec<(f32, f32)>,
+}
+
+/// What the enemy knows about the world this tick.
+pub(crate) struct Perception {
+    pub position: Vector,
+    pub health_ratio: f32,
+    /// Position of the `Targeting` target, if it has one.
+    pub target: Option<Vector>,
+    /// Whether an attack animation is still playing.
+    pub attacking: bool,
+}
+
+/// Orders for the other components.
+pub(crate) struct AiOutput {
+    pub move_target: Vector,
+    pub facing: Option<f32>,
+    pub start_attack: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct EnemyAi {
+    pub state: AiState,
+    /// Seconds spent in the current state.
+    pub timer: f32,
+    #[serde(with = "VectorDef")]
+    pub home: Vector,
+    pub patrol_index: usize,
+    pub tuning: AiTuning,
+}
+
+impl EnemyAi {
+    pub fn new(tuning: AiTuning, home: Vector) -> Self {
+        Self {
+            state: AiState::Idle,
+            timer: 0f32,
+            home,
+            patrol_index: 0,
+            tuning,
+        }
+    }
+
+    fn enter(&mut self, state: AiState) {
+        if self.state != state {
+            debug!("ai {:?} -> {:?}", self.state, state);
+        }
+        self.state = state;
+        self.timer = 0f32;
+    }
+
+    fn patrol_point(&self) -> Option<Vector> {
+        let patrol = &self.tuning.patrol;
+        if patrol.is_empty() {
+            return None;
+        }
+        let (x, y) = patrol[self.patrol_index % patrol.len()];
+        Some(self.home + Vector::new(x, y))
+    }
+
+    /// Runs one tick of the state machine.
+    pub fn update(&mut self, delta: f32, seen: &Perception) -> AiOutput {
+        self.timer += delta;
+        let tuning = self.tuning.clone();
+        let distance = seen.target.map(|t| t.distance(seen.position));
+        let in_sight = seen.target.is_some();
+        let scared = seen.health_ratio < tuning.flee_health;
+
+        match self.state {
+            AiState::Idle | AiState::Patrol if in_sight => self.enter(if scared {
+                AiState::Flee
+            } else {
+                AiState::Chase
+            }),
+            AiState::Idle if self.timer >= tuning.patrol_wait && self.patrol_point().is_some() => {
+                self.enter(AiState::Patrol)
+            }
+            AiState::Patrol => {
+                let arrived = self
+                    .patrol_point()
+                    .map_or(true, |p| p.distance(seen.position) <= ARRIVE_DISTANCE);
+                if arrived {
+                    self.patrol_index += 1;
+                    self.enter(AiState::Idle);
+                }
+            }
+            AiState::Chase if !in_sight => self.enter(AiState::Idle),
+            AiState::Chase if scared => self.enter(AiState::Flee),
+            AiState::Chase if distance.map_or(false, |d| d <= tuning.attack_range) => {
+                self.enter(AiState::Windup)
+            }
+            AiState::Windup if self.timer >= tuning.windup => {
+                self.enter(AiState::Attack);
+                return AiOutput {
+                    move_target: seen.position,
+                    facing: self.facing(seen),
+                    start_attack: true,
+                };
+            }
+            // `start_attack` plays the animation later in the same tick, so not attacking here
+            // means it ended or was refused for lack of stamina or a busy animator
+            AiState::Attack if !seen.attacking => self.enter(AiState::Recover),
+            AiState::Recover if self.timer >= tuning.recover => self.enter(AiState::Idle),
+            AiState::Flee if !in_sight => self.enter(AiState::Idle),
+            _ => {}
+        }
+
+        let hold = seen.position;
+        let (move_target, facing) = match self.state {
+            AiState::Idle => (hold, None),
+            AiState::Patrol => (self.patrol_point().unwrap_or(hold), None),
+            AiState::Chase => (seen.target.unwrap_or(hold), None),
+            AiState::Windup | AiState::Attack | AiState::Recover => (hold, self.facing(seen)),
+            AiState::Flee => {
+                let away = match seen.target {
+                    Some(target) if target != seen.position => {
+                        (seen.position - target).normalize() * FLEE_DISTANCE
+                    }
+                    _ => Vector::ZERO,
+                };
+                (seen.position + away, None)
+            }
+        };
+        AiOutput {
+            move_target,
+            facing,
+            start_attack: false,
+        }
+    }
+
+    fn facing(&self, seen: &Perception) -> Option<f32> {
+        seen.target
+            .filter(|t| *t != seen.position)
+            .map(|t| (t.y - seen.position.y).atan2(t.x - seen.position.x))
+    }
+}
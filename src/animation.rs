@@ -0,0 +1,331 @@
+use crate::assets::load_ron_dir;
+use crate::components::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Builtin animations, see `assets`.
+const BUILTIN_ANIMATIONS: &[(&str, &str)] = &[
+    ("wait", include_str!("../assets/animations/wait.ron")),
+    ("attack", include_str!("../assets/animations/attack.ron")),
+    ("attack2", include_str!("../assets/animations/attack2.ron")),
+    ("finisher", include_str!("../assets/animations/finisher.ron")),
+    ("damaged", include_str!("../assets/animations/damaged.ron")),
+    ("shoot", include_str!("../assets/animations/shoot.ron")),
+    ("stagger", include_str!("../assets/animations/stagger.ron")),
+    ("dash", include_str!("../assets/animations/dash.ron")),
+];
+
+#[derive(Debug)]
+pub(crate) enum AnimationError {
+    Io(io::Error),
+    Parse(String, ron::Error),
+    Invalid(String, String),
+    Unknown(String),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimationError::Io(e) => write!(f, "animation io error: {}", e),
+            AnimationError::Parse(name, e) => write!(f, "animation {}: {}", name, e),
+            AnimationError::Invalid(name, reason) => write!(f, "animation {}: {}", name, reason),
+            AnimationError::Unknown(name) => write!(f, "unknown animation {}", name),
+        }
+    }
+}
+
+impl From<io::Error> for AnimationError {
+    fn from(e: io::Error) -> Self {
+        AnimationError::Io(e)
+    }
+}
+
+/// How a value moves from one keyframe to the next.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum Ease {
+    /// Holds the value until the next keyframe.
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Default for Ease {
+    fn default() -> Self {
+        Ease::Linear
+    }
+}
+
+impl Ease {
+    /// Maps progress `t` in `[0, 1]` between two keyframes.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Ease::Step => 0f32,
+            Ease::Linear => t,
+            Ease::EaseIn => t * t,
+            Ease::EaseOut => t * (2f32 - t),
+            Ease::EaseInOut => (1f32 - (t * PI).cos()) / 2f32,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Keyframe {
+    pub frame: usize,
+    pub value: f32,
+    /// Curve towards the following keyframe.
+    #[serde(default)]
+    pub ease: Ease,
+}
+
+/// Sparse description of a `CharacterAnimFrame` animation, as written in
+/// `assets/animations/*.ron`. Tracks without keyframes keep the default value.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct AnimationAsset {
+    pub frames: usize,
+    #[serde(default)]
+    pub looped: bool,
+    #[serde(default)]
+    pub radius_scale: Vec<Keyframe>,
+    #[serde(default)]
+    pub weapon_direction: Vec<Keyframe>,
+    #[serde(default)]
+    pub move_forward: Vec<Keyframe>,
+    /// Frames on which a `Launcher` shoots.
+    #[serde(default)]
+    pub fire: Vec<usize>,
+}
+
+impl AnimationAsset {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.frames == 0 {
+            return Err("animation has no frames".to_string());
+        }
+        let tracks = [
+            ("radius_scale", &self.radius_scale),
+            ("weapon_direction", &self.weapon_direction),
+            ("move_forward", &self.move_forward),
+        ];
+        for (track, keys) in tracks.iter() {
+            let mut prev: Option<usize> = None;
+            for key in keys.iter() {
+                if key.frame > self.frames {
+                    return Err(format!(
+                        "{} keyframe at frame {} is past the last frame {}",
+                        track, key.frame, self.frames
+                    ));
+                }
+                if prev.map_or(false, |p| p >= key.frame) {
+                    return Err(format!(
+                        "{} keyframes are not in ascending frame order at frame {}",
+                        track, key.frame
+                    ));
+                }
+                if !key.value.is_finite() {
+                    return Err(format!("{} keyframe at frame {} is not finite", track, key.frame));
+                }
+                prev = Some(key.frame);
+            }
+        }
+        if let Some(frame) = self.fire.iter().find(|f| **f >= self.frames) {
+            return Err(format!(
+                "fire frame {} is past the last frame {}",
+                frame,
+                self.frames - 1
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bakes the keyframes into one `CharacterAnimFrame` per frame.
+    pub fn sample(&self) -> Animation<CharacterAnimFrame> {
+        let frames = (0..self.frames)
+            .map(|f| CharacterAnimFrame {
+                radius_scale: sample_track(&self.radius_scale, f, 1f32),
+                weapon_direction: sample_track(&self.weapon_direction, f, 0f32),
+                move_forward: sample_track(&self.move_forward, f, 0f32),
+                fire: self.fire.contains(&f),
+            })
+            .collect();
+        Animation::new(self.looped, frames)
+    }
+}
+
+fn sample_track(keys: &[Keyframe], frame: usize, default: f32) -> f32 {
+    let next = keys.iter().position(|k| k.frame > frame);
+    match next {
+        None => keys.last().map_or(default, |k| k.value),
+        Some(0) => keys[0].value,
+        Some(i) => {
+            let from = &keys[i - 1];
+            let to = &keys[i];
+            let t = (frame - from.frame) as f32 / (to.frame - from.frame) as f32;
+            from.value + (to.value - from.value) * from.ease.apply(t)
+        }
+    }
+}
+
+/// Sampled animations by name.
+#[derive(Default, Clone)]
+pub(crate) struct AnimationLibrary {
+    animations: HashMap<String, Animation<CharacterAnimFrame>>,
+}
+
+impl AnimationLibrary {
+    pub fn builtin() -> Self {
+        let mut library = Self::default();
+        for (name, source) in BUILTIN_ANIMATIONS {
+            library
+                .load_str(name, source)
+                .expect("builtin animations must be valid");
+        }
+        library
+    }
+
+    pub fn load_str(&mut self, name: &str, source: &str) -> Result<(), AnimationError> {
+        let asset: AnimationAsset =
+            ron::de::from_str(source).map_err(|e| AnimationError::Parse(name.to_string(), e))?;
+        asset
+            .validate()
+            .map_err(|reason| AnimationError::Invalid(name.to_string(), reason))?;
+        self.animations.insert(name.to_string(), asset.sample());
+        Ok(())
+    }
+
+    /// Loads every `*.ron` file in `dir`, named after the file stem.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), AnimationError> {
+        load_ron_dir(dir, |name, source| self.load_str(name, source))
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Animation<CharacterAnimFrame>, AnimationError> {
+        self.animations
+            .get(name)
+            .ok_or_else(|| AnimationError::Unknown(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(frame: usize, value: f32, ease: Ease) -> Keyframe {
+        Keyframe { frame, value, ease }
+    }
+
+    fn asset(frames: usize) -> AnimationAsset {
+        AnimationAsset {
+            frames,
+            looped: false,
+            radius_scale: Vec::new(),
+            weapon_direction: Vec::new(),
+            move_forward: Vec::new(),
+            fire: Vec::new(),
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn builtin_animations_are_valid() {
+        let library = AnimationLibrary::builtin();
+        for (name, _) in BUILTIN_ANIMATIONS.iter() {
+            assert!(library.get(name).is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_empty_animations() {
+        assert!(asset(0).validate().is_err());
+        match AnimationLibrary::default().load_str("empty", "(frames: 0)") {
+            Err(AnimationError::Invalid(name, _)) => assert_eq!(name, "empty"),
+            _ => panic!("expected an invalid animation"),
+        }
+    }
+
+    #[test]
+    fn rejects_keyframes_out_of_range() {
+        let mut animation = asset(4);
+        // a key on `frames` only shapes the curve towards the end
+        animation.move_forward = vec![key(0, 0f32, Ease::Linear), key(4, 1f32, Ease::Linear)];
+        assert!(animation.validate().is_ok());
+        animation.move_forward.push(key(5, 0f32, Ease::Linear));
+        let reason = animation.validate().unwrap_err();
+        assert!(reason.contains("move_forward"), "{}", reason);
+        assert!(reason.contains("past the last frame"), "{}", reason);
+    }
+
+    #[test]
+    fn rejects_unsorted_and_duplicate_keyframes() {
+        let mut animation = asset(8);
+        animation.radius_scale = vec![key(4, 1f32, Ease::Linear), key(2, 1f32, Ease::Linear)];
+        assert!(animation.validate().unwrap_err().contains("ascending"));
+        animation.radius_scale = vec![key(2, 1f32, Ease::Linear), key(2, 1f32, Ease::Linear)];
+        assert!(animation.validate().unwrap_err().contains("ascending"));
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_finite() {
+        let mut animation = asset(8);
+        animation.weapon_direction = vec![key(0, std::f32::NAN, Ease::Linear)];
+        assert!(animation.validate().unwrap_err().contains("not finite"));
+    }
+
+    #[test]
+    fn rejects_fire_frames_out_of_range() {
+        let mut animation = asset(4);
+        animation.fire = vec![3];
+        assert!(animation.validate().is_ok());
+        animation.fire = vec![1, 4];
+        assert!(animation.validate().unwrap_err().contains("fire frame 4"));
+    }
+
+    #[test]
+    fn samples_keyframe_values_exactly() {
+        let keys = [
+            key(2, 1f32, Ease::EaseIn),
+            key(6, 5f32, Ease::EaseOut),
+            key(10, 3f32, Ease::Step),
+        ];
+        // before the first and after the last key the nearest value holds
+        assert_close(sample_track(&keys, 0, 0f32), 1f32);
+        assert_close(sample_track(&keys, 2, 0f32), 1f32);
+        assert_close(sample_track(&keys, 6, 0f32), 5f32);
+        assert_close(sample_track(&keys, 10, 0f32), 3f32);
+        assert_close(sample_track(&keys, 12, 0f32), 3f32);
+        assert_close(sample_track(&[], 3, 7f32), 7f32);
+    }
+
+    #[test]
+    fn samples_eased_values_between_keyframes() {
+        let keys = [
+            key(0, 0f32, Ease::Linear),
+            key(4, 8f32, Ease::EaseIn),
+            key(8, 0f32, Ease::EaseOut),
+            key(12, 4f32, Ease::EaseInOut),
+            key(16, 0f32, Ease::Step),
+            key(18, 2f32, Ease::Linear),
+        ];
+        assert_close(sample_track(&keys, 2, 0f32), 4f32);
+        assert_close(sample_track(&keys, 6, 0f32), 8f32 - 8f32 * 0.25);
+        assert_close(sample_track(&keys, 10, 0f32), 4f32 * 0.75);
+        assert_close(sample_track(&keys, 14, 0f32), 2f32);
+        assert_close(sample_track(&keys, 17, 0f32), 0f32);
+        assert_close(sample_track(&keys, 18, 0f32), 2f32);
+    }
+
+    #[test]
+    fn eases_start_at_zero_and_end_at_one() {
+        for ease in [Ease::Linear, Ease::EaseIn, Ease::EaseOut, Ease::EaseInOut].iter() {
+            assert_close(ease.apply(0f32), 0f32);
+            assert_close(ease.apply(1f32), 1f32);
+        }
+        assert_close(Ease::Step.apply(1f32), 0f32);
+    }
+}
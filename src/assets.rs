@@ -0,0 +1,26 @@
+//! Data files under `assets/`. Every kind of asset is also compiled into the binary as a
+//! `BUILTIN_*` table, so the game runs without an assets directory; natively, the files found
+//! at startup replace the builtin ones of the same name.
+
+use std::io;
+use std::path::Path;
+
+/// Calls `load` with the file stem and the contents of every `*.ron` file in `dir`, stopping
+/// at the first error.
+pub(crate) fn load_ron_dir<P, E, F>(dir: P, mut load: F) -> Result<(), E>
+where
+    P: AsRef<Path>,
+    E: From<io::Error>,
+    F: FnMut(&str, &str) -> Result<(), E>,
+{
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "ron") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+            load(name, &std::fs::read_to_string(&path)?)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,513 @@
+use crate::assets::load_ron_dir;
+use crate::components::Input;
+use crate::snapshot::VectorDef;
+use quicksilver::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Builtin behavior trees, see `assets`.
+const BUILTIN_BEHAVIORS: &[(&str, &str)] =
+    &[("guard", include_str!("../assets/behaviors/guard.ron"))];
+
+/// Distance in pixels at which `ReturnHome` counts as arrived.
+const HOME_DISTANCE: f32 = 4f32;
+
+#[derive(Debug)]
+pub(crate) enum BehaviorError {
+    Io(io::Error),
+    Parse(String, ron::Error),
+    Unknown(String),
+}
+
+impl fmt::Display for BehaviorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BehaviorError::Io(e) => write!(f, "behavior io error: {}", e),
+            BehaviorError::Parse(name, e) => write!(f, "behavior {}: {}", name, e),
+            BehaviorError::Unknown(name) => write!(f, "unknown behavior {}", name),
+        }
+    }
+}
+
+impl From<io::Error> for BehaviorError {
+    fn from(e: io::Error) -> Self {
+        BehaviorError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Condition {
+    HasTarget,
+    /// The target is at most this many pixels away.
+    TargetWithin(f32),
+    /// The target is swinging its weapon.
+    TargetAttacking,
+    /// Health ratio below this.
+    HealthBelow(f32),
+    /// Not busy with an attack, a dash or a stagger.
+    CanAct,
+    /// A blackboard flag written by `Action::Set`.
+    Flag(String),
+}
+
+impl Condition {
+    fn check(&self, board: &Blackboard) -> bool {
+        match self {
+            Condition::HasTarget => board.target.is_some(),
+            Condition::TargetWithin(distance) => board
+                .target
+                .map_or(false, |t| t.distance(board.position) <= *distance),
+            Condition::TargetAttacking => board.target_attacking,
+            Condition::HealthBelow(ratio) => board.health_ratio < *ratio,
+            Condition::CanAct => board.can_act,
+            Condition::Flag(name) => board.flags.get(name).cloned().unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Action {
+    /// Walks to the target; running as long as there is one.
+    Chase,
+    /// Walks straight away from the target, aiming this many pixels ahead.
+    Flee(f32),
+    /// Walks back to the spawn position.
+    ReturnHome,
+    Hold,
+    FaceTarget,
+    /// Presses attack; fails while the character can't act.
+    Attack,
+    /// Holds the shield up for this tick.
+    Defend,
+    /// Presses dash; fails while the character can't act.
+    Dash,
+    /// Running for this many seconds, then succeeds.
+    Wait(f32),
+    /// Writes a blackboard flag.
+    Set(String, bool),
+}
+
+impl Action {
+    fn run(
+        &self,
+        id: usize,
+        delta: f32,
+        board: &mut Blackboard,
+        out: &mut BehaviorOutput,
+    ) -> Status {
+        let press = |can_act: bool, button: &mut bool| {
+            if can_act {
+                *button = true;
+                Status::Success
+            } else {
+                Status::Failure
+            }
+        };
+        match self {
+            Action::Chase => match board.target {
+                Some(target) => {
+                    out.move_target = target;
+                    Status::Running
+                }
+                None => Status::Failure,
+            },
+            Action::Flee(distance) => match board.target {
+                Some(target) => {
+                    let away = if target != board.position {
+                        (board.position - target).normalize()
+                    } else {
+                        Vector::new(1f32, 0f32)
+                    };
+                    out.move_target = board.position + away * *distance;
+                    Status::Running
+                }
+                None => Status::Failure,
+            },
+            Action::ReturnHome => {
+                if board.home.distance(board.position) <= HOME_DISTANCE {
+                    Status::Success
+                } else {
+                    out.move_target = board.home;
+                    Status::Running
+                }
+            }
+            Action::Hold => {
+                out.move_target = board.position;
+                Status::Success
+            }
+            Action::FaceTarget => match board.target {
+                Some(target) if target != board.position => {
+                    let offset = target - board.position;
+                    out.facing = Some(offset.y.atan2(offset.x));
+                    Status::Success
+                }
+                _ => Status::Failure,
+            },
+            Action::Attack => press(board.can_act, &mut out.input.attack),
+            Action::Defend => press(true, &mut out.input.defend),
+            Action::Dash => press(board.can_act, &mut out.input.dash),
+            Action::Wait(seconds) => {
+                let waited = board.waits.entry(id).or_insert(0f32);
+                *waited += delta;
+                if *waited >= *seconds {
+                    board.waits.remove(&id);
+                    Status::Success
+                } else {
+                    Status::Running
+                }
+            }
+            Action::Set(flag, value) => {
+                board.flags.insert(flag.clone(), *value);
+                Status::Success
+            }
+        }
+    }
+}
+
+/// A behavior tree as written in `assets/behaviors/*.ron`. The tree is evaluated from the
+/// root every tick: selectors re-check their higher priority branches each time, while a
+/// running sequence checks its conditions again and resumes from the child that was running.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum BehaviorNode {
+    /// Runs the children in order until one fails or is running. When resuming a running
+    /// child, the `Condition` children before it must still hold.
+    Sequence(Vec<BehaviorNode>),
+    /// Runs the children in order until one succeeds or is running.
+    Selector(Vec<BehaviorNode>),
+    /// Swaps success and failure.
+    Invert(Box<BehaviorNode>),
+    /// Succeeds once the child is done, whatever its result.
+    Succeed(Box<BehaviorNode>),
+    /// Fails without running the child for this many seconds after it succeeded.
+    Cooldown(f32, Box<BehaviorNode>),
+    Condition(Condition),
+    Action(Action),
+}
+
+impl BehaviorNode {
+    /// Number of nodes in the subtree. Nodes are indexed in depth first order, which is how
+    /// the blackboard keeps their state apart.
+    fn size(&self) -> usize {
+        match self {
+            BehaviorNode::Sequence(children) | BehaviorNode::Selector(children) => {
+                1 + children.iter().map(|c| c.size()).sum::<usize>()
+            }
+            BehaviorNode::Invert(child)
+            | BehaviorNode::Succeed(child)
+            | BehaviorNode::Cooldown(_, child) => 1 + child.size(),
+            BehaviorNode::Condition(_) | BehaviorNode::Action(_) => 1,
+        }
+    }
+
+    fn tick(
+        &self,
+        id: usize,
+        delta: f32,
+        board: &mut Blackboard,
+        out: &mut BehaviorOutput,
+    ) -> Status {
+        board.visited.insert(id);
+        match self {
+            BehaviorNode::Sequence(children) => {
+                let start = board.running.remove(&id).unwrap_or(0).min(children.len());
+                let mut child_id = id + 1;
+                for child in children[..start].iter() {
+                    if let BehaviorNode::Condition(_) = child {
+                        if child.tick(child_id, delta, board, out) == Status::Failure {
+                            return Status::Failure;
+                        }
+                    }
+                    child_id += child.size();
+                }
+                for (i, child) in children.iter().enumerate().skip(start) {
+                    match child.tick(child_id, delta, board, out) {
+                        Status::Success => child_id += child.size(),
+                        Status::Failure => return Status::Failure,
+                        Status::Running => {
+                            board.running.insert(id, i);
+                            return Status::Running;
+                        }
+                    }
+                }
+                Status::Success
+            }
+            BehaviorNode::Selector(children) => {
+                let mut child_id = id + 1;
+                for child in children.iter() {
+                    match child.tick(child_id, delta, board, out) {
+                        Status::Failure => child_id += child.size(),
+                        status => return status,
+                    }
+                }
+                Status::Failure
+            }
+            BehaviorNode::Invert(child) => match child.tick(id + 1, delta, board, out) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            BehaviorNode::Succeed(child) => match child.tick(id + 1, delta, board, out) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            BehaviorNode::Cooldown(seconds, child) => {
+                if board.cooldowns.contains_key(&id) {
+                    return Status::Failure;
+                }
+                let status = child.tick(id + 1, delta, board, out);
+                if status == Status::Success {
+                    board.cooldowns.insert(id, *seconds);
+                }
+                status
+            }
+            BehaviorNode::Condition(condition) => {
+                if condition.check(board) {
+                    Status::Success
+                } else {
+                    Status::Failure
+                }
+            }
+            BehaviorNode::Action(action) => action.run(id, delta, board, out),
+        }
+    }
+
+    /// Runs the tree once from the root. Sequences and waits that were not reached this time
+    /// lose their progress.
+    pub fn run(&self, delta: f32, board: &mut Blackboard) -> BehaviorOutput {
+        for remaining in board.cooldowns.values_mut() {
+            *remaining -= delta;
+        }
+        board.cooldowns.retain(|_, remaining| *remaining > 0f32);
+        board.visited.clear();
+
+        let mut out = BehaviorOutput {
+            move_target: board.position,
+            facing: None,
+            input: Input::default(),
+        };
+        self.tick(0, delta, board, &mut out);
+
+        let visited = &board.visited;
+        board.running.retain(|id, _| visited.contains(id));
+        board.waits.retain(|id, _| visited.contains(id));
+        out
+    }
+}
+
+/// Per entity memory of a tree. The perception fields are refreshed by `behavior_trees`
+/// before every tick; the rest is written by the nodes themselves.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Blackboard {
+    #[serde(with = "VectorDef")]
+    pub home: Vector,
+    #[serde(skip)]
+    pub position: Vector,
+    /// Position of the `Targeting` target.
+    #[serde(skip)]
+    pub target: Option<Vector>,
+    #[serde(skip)]
+    pub target_attacking: bool,
+    #[serde(skip)]
+    pub health_ratio: f32,
+    #[serde(skip)]
+    pub can_act: bool,
+    pub flags: BTreeMap<String, bool>,
+    /// Child each running sequence resumes from, by node index.
+    running: BTreeMap<usize, usize>,
+    /// Seconds spent in each running `Wait`, by node index.
+    waits: BTreeMap<usize, f32>,
+    /// Seconds left on each `Cooldown`, by node index.
+    cooldowns: BTreeMap<usize, f32>,
+    #[serde(skip)]
+    visited: BTreeSet<usize>,
+}
+
+/// What a tree asks of its character this tick.
+pub(crate) struct BehaviorOutput {
+    pub move_target: Vector,
+    pub facing: Option<f32>,
+    pub input: Input,
+}
+
+/// Runs the named tree of the `BehaviorLibrary` every tick.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Behavior {
+    pub tree: String,
+    pub blackboard: Blackboard,
+}
+
+impl Behavior {
+    pub fn new(tree: String, home: Vector) -> Self {
+        Self {
+            tree,
+            blackboard: Blackboard {
+                home,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Buttons pressed by the AI systems. Attacks, dashes and the shield read it like the
+/// player's `Input`, so AI actions pay stamina the same way; movement goes through
+/// `MoveTarget` instead.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct AiInput(pub Input);
+
+/// Behavior trees by name.
+#[derive(Default, Clone)]
+pub(crate) struct BehaviorLibrary {
+    trees: HashMap<String, BehaviorNode>,
+}
+
+impl BehaviorLibrary {
+    pub fn builtin() -> Self {
+        let mut library = Self::default();
+        for (name, source) in BUILTIN_BEHAVIORS {
+            library
+                .load_str(name, source)
+                .expect("builtin behaviors must parse");
+        }
+        library
+    }
+
+    pub fn load_str(&mut self, name: &str, source: &str) -> Result<(), BehaviorError> {
+        let tree =
+            ron::de::from_str(source).map_err(|e| BehaviorError::Parse(name.to_string(), e))?;
+        self.trees.insert(name.to_string(), tree);
+        Ok(())
+    }
+
+    /// Loads every `*.ron` file in `dir`, named after the file stem.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), BehaviorError> {
+        load_ron_dir(dir, |name, source| self.load_str(name, source))
+    }
+
+    pub fn get(&self, name: &str) -> Result<&BehaviorNode, BehaviorError> {
+        self.trees
+            .get(name)
+            .ok_or_else(|| BehaviorError::Unknown(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: f32 = 0.125f32;
+
+    fn action(action: Action) -> BehaviorNode {
+        BehaviorNode::Action(action)
+    }
+
+    fn condition(condition: Condition) -> BehaviorNode {
+        BehaviorNode::Condition(condition)
+    }
+
+    fn board(target: Option<Vector>) -> Blackboard {
+        Blackboard {
+            home: Vector::new(-100f32, 0f32),
+            target,
+            health_ratio: 1f32,
+            can_act: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builtin_trees_parse() {
+        assert!(BehaviorLibrary::builtin().get("guard").is_ok());
+    }
+
+    #[test]
+    fn selector_runs_the_first_branch_that_does_not_fail() {
+        let tree = BehaviorNode::Selector(vec![
+            BehaviorNode::Sequence(vec![condition(Condition::HasTarget), action(Action::Chase)]),
+            action(Action::ReturnHome),
+        ]);
+        let target = Vector::new(50f32, 0f32);
+        let mut board = board(Some(target));
+        assert_eq!(tree.run(DELTA, &mut board).move_target, target);
+        board.target = None;
+        assert_eq!(tree.run(DELTA, &mut board).move_target, board.home);
+    }
+
+    #[test]
+    fn wait_runs_for_its_duration() {
+        let tree = BehaviorNode::Sequence(vec![
+            action(Action::Wait(2f32 * DELTA)),
+            action(Action::Attack),
+        ]);
+        let mut board = board(None);
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        assert!(tree.run(DELTA, &mut board).input.attack);
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        assert!(tree.run(DELTA, &mut board).input.attack);
+    }
+
+    #[test]
+    fn wait_starts_over_when_not_reached() {
+        let tree = BehaviorNode::Selector(vec![
+            BehaviorNode::Sequence(vec![
+                condition(Condition::Flag("busy".to_string())),
+                action(Action::Hold),
+            ]),
+            BehaviorNode::Sequence(vec![
+                action(Action::Wait(2f32 * DELTA)),
+                action(Action::Attack),
+            ]),
+        ]);
+        let mut board = board(None);
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        board.flags.insert("busy".to_string(), true);
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        board.flags.insert("busy".to_string(), false);
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        assert!(tree.run(DELTA, &mut board).input.attack);
+    }
+
+    #[test]
+    fn cooldown_fails_until_it_is_over() {
+        let tree = BehaviorNode::Cooldown(2f32 * DELTA, Box::new(action(Action::Attack)));
+        let mut board = board(None);
+        assert!(tree.run(DELTA, &mut board).input.attack);
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        assert!(tree.run(DELTA, &mut board).input.attack);
+    }
+
+    #[test]
+    fn cooldown_starts_only_on_success() {
+        let tree = BehaviorNode::Cooldown(2f32 * DELTA, Box::new(action(Action::Attack)));
+        let mut board = board(None);
+        board.can_act = false;
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        board.can_act = true;
+        assert!(tree.run(DELTA, &mut board).input.attack);
+    }
+
+    #[test]
+    fn running_sequence_checks_its_conditions_again() {
+        let tree = BehaviorNode::Sequence(vec![
+            condition(Condition::TargetWithin(30f32)),
+            action(Action::Wait(2f32 * DELTA)),
+            action(Action::Attack),
+        ]);
+        let mut board = board(Some(Vector::new(20f32, 0f32)));
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        board.target = Some(Vector::new(200f32, 0f32));
+        assert!(!tree.run(DELTA, &mut board).input.attack);
+        assert!(board.running.is_empty());
+        assert!(board.waits.is_empty());
+    }
+}
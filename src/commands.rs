@@ -0,0 +1,155 @@
+use crate::components::*;
+use crate::prefab::*;
+use crate::*;
+use quicksilver::prelude::*;
+use static_ecs::*;
+
+/// Components that can be added to or removed from an existing entity through a
+/// `CommandBuffer`.
+pub(crate) trait WorldComponent: Sized + 'static {
+    fn insert(world: &mut World, entity_id: EntityID, component: Self);
+    fn remove(world: &mut World, entity_id: EntityID);
+}
+
+macro_rules! world_component {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl WorldComponent for $t {
+                fn insert(world: &mut World, entity_id: EntityID, component: Self) {
+                    component_mut!(world, $t).insert(entity_id, component);
+                }
+                fn remove(world: &mut World, entity_id: EntityID) {
+                    component_mut!(world, $t).remove(entity_id);
+                }
+            }
+        )*
+    };
+}
+
+world_component!(
+    Input,
+    Team,
+    Health,
+    SwordCollider,
+    BodyWeaponCollider,
+    BodyDefenseCollider,
+    MoveTarget,
+    Position,
+    Direction,
+    Velocity,
+    CharacterAnimator,
+    StatusBarView<ForHealth>,
+    CharacterView,
+    Weapon,
+    Armor,
+    HitRegistry,
+    Invulnerable,
+    Mass,
+    Knockback,
+    Projectile,
+    ProjectileCollider,
+    ProjectileView,
+    Launcher,
+    KeepDistance,
+    Shield,
+    ShieldCollider,
+    Stamina,
+    StatusBarView<ForStamina>,
+    StatusEffects,
+    Hazard,
+    Combo,
+    Pickup,
+    PickupCollider,
+    PickupView,
+    Collector,
+    DamageBoost,
+    Loot,
+    EnemyAi,
+    Targeting,
+    TargetPriority,
+    StaticCollider,
+    PathFollower,
+    Steering,
+    Behavior,
+    AiInput,
+);
+
+pub(crate) enum Command {
+    Spawn { prefab: String, position: Vector },
+    Despawn(EntityID),
+    Custom(Box<dyn FnOnce(&mut World, &PrefabRegistry)>),
+}
+
+/// Structural changes requested by systems. The scheduler applies them at the end of every
+/// stage, so systems never see an entity appear or vanish halfway through a stage.
+#[derive(Default)]
+pub(crate) struct CommandBuffer {
+    commands: Vec<Command>,
+    /// Highest id of a despawned entity. The live entities alone don't tell which ids the
+    /// world has already handed out, so snapshots keep it.
+    retired: Option<EntityID>,
+}
+
+impl CommandBuffer {
+    pub fn push(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+    pub fn spawn(&mut self, prefab: &str, position: Vector) {
+        self.push(Command::Spawn {
+            prefab: prefab.to_string(),
+            position,
+        });
+    }
+    pub fn despawn(&mut self, entity_id: EntityID) {
+        self.push(Command::Despawn(entity_id));
+    }
+    pub fn add_component<T: WorldComponent>(&mut self, entity_id: EntityID, component: T) {
+        self.push(Command::Custom(Box::new(move |world, _| {
+            T::insert(world, entity_id, component)
+        })));
+    }
+    pub fn remove_component<T: WorldComponent>(&mut self, entity_id: EntityID) {
+        self.push(Command::Custom(Box::new(move |world, _| {
+            T::remove(world, entity_id)
+        })));
+    }
+    /// Runs `f` at the sync point with full access to the world, e.g. to spawn an entity and
+    /// then customise its components.
+    pub fn run<F: FnOnce(&mut World, &PrefabRegistry) + 'static>(&mut self, f: F) {
+        self.push(Command::Custom(Box::new(f)));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+    /// Highest id despawned so far, or passed to `retire`.
+    pub fn retired(&self) -> Option<EntityID> {
+        self.retired
+    }
+    /// Counts `entity_id` as handed out, e.g. the last id of a loaded snapshot.
+    pub fn retire(&mut self, entity_id: Option<EntityID>) {
+        self.retired = self.retired.max(entity_id);
+    }
+
+    /// Applies the queued commands in the order they were pushed.
+    pub fn apply(&mut self, world: &mut World, prefabs: &PrefabRegistry) {
+        let mut despawned = Vec::new();
+        for command in self.commands.drain(..) {
+            match command {
+                Command::Spawn { prefab, position } => {
+                    if let Err(e) = prefabs.spawn_prefab(world, &prefab, position) {
+                        error!("{}", e);
+                    }
+                }
+                Command::Despawn(entity_id) => {
+                    // several systems may ask for the same entity to go away
+                    if !despawned.contains(&entity_id) {
+                        world.remove_component(entity_id);
+                        despawned.push(entity_id);
+                        self.retired = self.retired.max(Some(entity_id));
+                    }
+                }
+                Command::Custom(f) => f(world, prefabs),
+            }
+        }
+    }
+}
@@ -1,9 +1,12 @@
+use crate::snapshot::{CircleDef, ColorDef, LineDef, VectorDef};
+use crate::{CharacterAnimID, CharacterAnimator};
 use quicksilver::prelude::*;
+use static_ecs::*;
+use serde::{Deserialize, Serialize};
 use std::collections::*;
-use std::hash::Hash;
 use std::marker::PhantomData;
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct Team {
     team_id: u32,
 }
@@ -17,46 +20,105 @@ impl Team {
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct Input {
     pub left: bool,
     pub right: bool,
     pub up: bool,
     pub down: bool,
     pub attack: bool,
+    pub defend: bool,
+    pub dash: bool,
 }
 
-#[derive(Default, Clone)]
-pub(crate) struct MoveTarget(pub Vector);
+impl Input {
+    const LEFT: u8 = 1 << 0;
+    const RIGHT: u8 = 1 << 1;
+    const UP: u8 = 1 << 2;
+    const DOWN: u8 = 1 << 3;
+    const ATTACK: u8 = 1 << 4;
+    const DEFEND: u8 = 1 << 5;
+    const DASH: u8 = 1 << 6;
 
-#[derive(Default, Clone)]
-pub(crate) struct Velocity(pub Vector);
+    /// Packs the buttons into one byte, one bit per button.
+    pub fn to_bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.left {
+            bits |= Self::LEFT;
+        }
+        if self.right {
+            bits |= Self::RIGHT;
+        }
+        if self.up {
+            bits |= Self::UP;
+        }
+        if self.down {
+            bits |= Self::DOWN;
+        }
+        if self.attack {
+            bits |= Self::ATTACK;
+        }
+        if self.defend {
+            bits |= Self::DEFEND;
+        }
+        if self.dash {
+            bits |= Self::DASH;
+        }
+        bits
+    }
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            left: bits & Self::LEFT != 0,
+            right: bits & Self::RIGHT != 0,
+            up: bits & Self::UP != 0,
+            down: bits & Self::DOWN != 0,
+            attack: bits & Self::ATTACK != 0,
+            defend: bits & Self::DEFEND != 0,
+            dash: bits & Self::DASH != 0,
+        }
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct MoveTarget(#[serde(with = "VectorDef")] pub Vector);
 
-#[derive(Default, Clone)]
-pub(crate) struct Position(pub Vector);
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Velocity(#[serde(with = "VectorDef")] pub Vector);
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Position(#[serde(with = "VectorDef")] pub Vector);
 
 pub(crate) type Direction = f32;
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct CharacterView {
+    #[serde(with = "VectorDef")]
     pub position: Vector,
     pub direction: f32,
     pub radius: f32,
     pub radius_scale: f32,
+    #[serde(with = "ColorDef")]
     pub color: Color,
+    /// Prefab color, before status effect tints.
+    #[serde(with = "ColorDef")]
+    pub base_color: Color,
     pub weapon_direction: f32,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ForHealth();
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct ForStamina();
 // Heat(()),
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct StatusBarView<T> {
+    #[serde(with = "VectorDef")]
     pub position: Vector,
+    #[serde(with = "ColorDef")]
     pub color: Color,
     pub frame_length: i32,
-    pub animated_length: i32,
+    pub animated_length: f32,
     pub current_length: i32,
     phantom: PhantomData<T>,
 }
@@ -68,52 +130,78 @@ impl<T> StatusBarView<T> {
             color: color,
             frame_length: length,
             current_length: length,
-            animated_length: length,
+            animated_length: length as f32,
             phantom: PhantomData,
         }
     }
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.current_length = (self.frame_length as f32 * ratio) as i32;
+    }
+    /// Moves the trailing `animated_length` towards `current_length` by at most `step`.
+    pub fn animate(&mut self, step: f32) {
+        let diff = self.current_length as f32 - self.animated_length;
+        if diff.abs() <= step {
+            self.animated_length = self.current_length as f32;
+        } else {
+            self.animated_length += step * diff.signum();
+        }
+    }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct CharacterAnimFrame {
     pub radius_scale: f32,
     pub weapon_direction: f32,
+    /// Pixels per second along the character's facing.
     pub move_forward: f32,
+    /// A `Launcher` fires its projectile when the animation reaches this frame.
+    pub fire: bool,
 }
 
-#[derive(Default, Clone)]
+/// Animations are authored as one value per frame at this rate.
+pub(crate) const ANIMATION_FPS: f32 = 60f32;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct Animator<K, V>
 where
-    K: Hash + Eq,
+    K: Ord,
 {
     playing_id: Option<K>,
     current_frame: usize,
-    animations: HashMap<K, Animation<V>>,
+    elapsed: f32,
+    play_count: u32,
+    animations: BTreeMap<K, Animation<V>>,
 }
 
 impl<K, V> Animator<K, V>
 where
-    K: Hash + Eq + Copy,
+    K: Ord + Copy,
 {
     pub fn play(&mut self, animation_id: K) {
         if self.animations.contains_key(&animation_id) {
             self.playing_id = Some(animation_id);
             self.current_frame = 0;
+            self.elapsed = 0f32;
+            self.play_count = self.play_count.wrapping_add(1);
         }
     }
     pub fn is_end(&self) -> bool {
         if let Some(id) = self.playing_id {
             let anim = self.animations.get(&id).unwrap();
-            return !anim.looped && self.current_frame == anim.values.len();
+            return !anim.looped && self.current_frame >= anim.values.len();
         }
         return false;
     }
-    pub fn update(&mut self) {
+    pub fn update(&mut self, delta: f32) {
         if let Some(id) = self.playing_id {
             if let Some(anim) = self.animations.get(&id) {
-                self.current_frame += 1;
+                self.elapsed += delta;
+                // the small bias keeps accumulated rounding error from landing a tick short
+                self.current_frame = (self.elapsed * ANIMATION_FPS + 0.001f32) as usize;
                 if anim.values.len() <= self.current_frame && anim.looped {
-                    self.current_frame = 0;
+                    self.elapsed -= anim.values.len() as f32 / ANIMATION_FPS;
+                    self.current_frame = (self.elapsed * ANIMATION_FPS + 0.001f32) as usize
+                        % anim.values.len().max(1);
                 }
             }
         }
@@ -129,9 +217,22 @@ where
     pub fn playing_id(&self) -> Option<K> {
         self.playing_id
     }
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+    /// Frame count of the playing animation.
+    pub fn frames(&self) -> usize {
+        self.playing_id
+            .and_then(|id| self.animations.get(&id))
+            .map_or(0, |anim| anim.values.len())
+    }
+    /// Changes every time `play` starts an animation, even the one already playing.
+    pub fn play_count(&self) -> u32 {
+        self.play_count
+    }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct Animation<T> {
     looped: bool,
     values: Vec<T>,
@@ -148,9 +249,10 @@ impl<T> Animation<T> {
 //     pub hit: bool,
 // }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct SwordCollider {
     pub active: bool,
+    #[serde(with = "LineDef")]
     pub line: quicksilver::geom::Line,
 }
 impl SwordCollider {
@@ -163,8 +265,9 @@ impl SwordCollider {
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct BodyWeaponCollider {
+    #[serde(with = "CircleDef")]
     pub circle: quicksilver::geom::Circle,
 }
 
@@ -174,13 +277,13 @@ impl BodyWeaponCollider {
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct BodyDefenseCollider {
-    pub hit: bool,
+    #[serde(with = "CircleDef")]
     pub circle: quicksilver::geom::Circle,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct Health {
     pub max_health: i32,
     pub current_health: i32,
@@ -198,6 +301,110 @@ impl Health {
     }
 }
 
+/// Spent by attacks, dashes and blocks; actions are refused when it runs short.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Stamina {
+    pub max: f32,
+    #[serde(default)]
+    pub current: f32,
+    /// Points regained per second.
+    pub regen: f32,
+    /// Seconds after spending before regeneration resumes.
+    pub regen_delay: f32,
+    #[serde(default)]
+    pub delay_remaining: f32,
+    pub attack_cost: f32,
+    pub dash_cost: f32,
+    /// Spent for every hit the shield blocks.
+    pub block_cost: f32,
+}
+
+impl Stamina {
+    pub fn can_afford(&self, cost: f32) -> bool {
+        self.current >= cost
+    }
+    pub fn spend(&mut self, cost: f32) {
+        self.current = (self.current - cost).max(0f32);
+        self.delay_remaining = self.regen_delay;
+    }
+    pub fn update(&mut self, delta: f32) {
+        if self.delay_remaining > 0f32 {
+            self.delay_remaining = (self.delay_remaining - delta).max(0f32);
+        } else {
+            self.current = (self.current + self.regen * delta).min(self.max);
+        }
+    }
+    pub fn ratio(&self) -> f32 {
+        if self.max <= 0f32 {
+            return 0f32;
+        }
+        self.current / self.max
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum DamageType {
+    Slash,
+    Blunt,
+    Pierce,
+    Fire,
+    Poison,
+}
+
+impl Default for DamageType {
+    fn default() -> Self {
+        DamageType::Slash
+    }
+}
+
+/// Damage dealt by whatever an entity attacks with. Attackers without one deal
+/// `Weapon::default()`.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Weapon {
+    pub base_damage: i32,
+    #[serde(default)]
+    pub damage_type: DamageType,
+    /// Speed in pixels per second the victim is pushed away with, before its `Mass`.
+    #[serde(default = "Weapon::default_knockback")]
+    pub knockback: f32,
+    /// Inflicted on every victim the weapon damages.
+    #[serde(default)]
+    pub effects: Vec<StatusEffect>,
+}
+
+impl Default for Weapon {
+    fn default() -> Self {
+        Self {
+            base_damage: 10,
+            damage_type: DamageType::Slash,
+            knockback: Self::default_knockback(),
+            effects: Vec::new(),
+        }
+    }
+}
+
+impl Weapon {
+    fn default_knockback() -> f32 {
+        480f32
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Armor {
+    /// Subtracted from every hit after resistances.
+    #[serde(default)]
+    pub flat_reduction: i32,
+    /// Fraction of damage of a type that is ignored, `1.0` meaning immune.
+    #[serde(default)]
+    pub resistances: BTreeMap<DamageType, f32>,
+}
+
+impl Armor {
+    pub fn resistance(&self, damage_type: DamageType) -> f32 {
+        self.resistances.get(&damage_type).cloned().unwrap_or(0f32)
+    }
+}
+
 pub(crate) struct ValueObserver<V, C> {
     prev_changed: bool,
     changed: bool,
@@ -233,3 +440,828 @@ where
         self.prev_changed
     }
 }
+
+/// Victims already struck by the current attack of an entity, so one attack damages each
+/// victim at most once.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct HitRegistry {
+    victims: Vec<EntityID>,
+    /// `Animator::play_count` of the attack the victims belong to.
+    attack: u32,
+    /// Attackers without animations, like body contact, start a new attack this often.
+    pub rehit_interval: Option<f32>,
+    rehit_elapsed: f32,
+}
+
+impl HitRegistry {
+    pub fn new(rehit_interval: Option<f32>) -> Self {
+        Self {
+            rehit_interval,
+            ..Default::default()
+        }
+    }
+    pub fn contains(&self, victim: EntityID) -> bool {
+        self.victims.contains(&victim)
+    }
+    pub fn register(&mut self, victim: EntityID) {
+        if !self.contains(victim) {
+            self.victims.push(victim);
+        }
+    }
+    /// Forgets the victims when `attack` differs from the attack they were registered for.
+    pub fn start_attack(&mut self, attack: u32) {
+        if self.attack != attack {
+            self.attack = attack;
+            self.victims.clear();
+        }
+    }
+    pub fn update(&mut self, delta: f32) {
+        if let Some(interval) = self.rehit_interval {
+            self.rehit_elapsed += delta;
+            if self.rehit_elapsed >= interval {
+                self.rehit_elapsed = 0f32;
+                self.victims.clear();
+            }
+        }
+    }
+}
+
+/// Ignores hits for `duration` seconds after taking damage.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Invulnerable {
+    pub duration: f32,
+    remaining: f32,
+}
+
+impl Invulnerable {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            duration,
+            remaining: 0f32,
+        }
+    }
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0f32
+    }
+    pub fn trigger(&mut self) {
+        self.remaining = self.duration;
+    }
+    pub fn update(&mut self, delta: f32) {
+        self.remaining = (self.remaining - delta).max(0f32);
+    }
+}
+
+/// How hard an entity is to push around. Impulses are reduced by `poise` and the rest is
+/// divided by `mass`. Entities without one have a mass of 1 and no poise.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Mass {
+    pub mass: f32,
+    #[serde(default)]
+    pub poise: f32,
+}
+
+impl Default for Mass {
+    fn default() -> Self {
+        Self {
+            mass: 1f32,
+            poise: 0f32,
+        }
+    }
+}
+
+impl Mass {
+    pub fn response(&self, impulse: Vector) -> Vector {
+        let strength = impulse.len();
+        if strength <= self.poise || strength == 0f32 {
+            return Vector::ZERO;
+        }
+        impulse * ((strength - self.poise) / strength / self.mass.max(0.01f32))
+    }
+}
+
+/// Push from being hit, added on top of the velocity and fading out over time.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Knockback {
+    #[serde(with = "VectorDef")]
+    pub velocity: Vector,
+    /// Fraction of the velocity lost per second, exponentially.
+    pub decay: f32,
+}
+
+impl Default for Knockback {
+    fn default() -> Self {
+        Self {
+            velocity: Vector::ZERO,
+            decay: 10f32,
+        }
+    }
+}
+
+impl Knockback {
+    /// Below this speed the push is dropped altogether.
+    const REST_SPEED: f32 = 1f32;
+
+    pub fn push(&mut self, velocity: Vector) {
+        self.velocity += velocity;
+    }
+    pub fn update(&mut self, delta: f32) {
+        self.velocity *= (-self.decay * delta).exp();
+        if self.velocity.len() < Self::REST_SPEED {
+            self.velocity = Vector::ZERO;
+        }
+    }
+}
+
+/// A flying attack. Its damage comes from its own `Weapon` and it carries the `Team` of the
+/// character that shot it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Projectile {
+    pub owner: EntityID,
+    /// Seconds left before it disappears.
+    pub lifetime: f32,
+    /// Pixels left before it disappears.
+    pub range: f32,
+}
+
+impl Projectile {
+    pub fn is_spent(&self) -> bool {
+        self.lifetime <= 0f32 || self.range <= 0f32
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct ProjectileCollider {
+    #[serde(with = "CircleDef")]
+    pub circle: quicksilver::geom::Circle,
+}
+
+impl ProjectileCollider {
+    pub fn is_collided(&self, body: &BodyDefenseCollider) -> bool {
+        body.circle.overlaps(&self.circle)
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct ProjectileView {
+    #[serde(with = "VectorDef")]
+    pub position: Vector,
+    pub radius: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+}
+
+/// Shoots the named projectile on the `fire` frames of the character's animations.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Launcher {
+    pub projectile: String,
+    /// `Animator::play_count` of the animation that last fired, so each play fires once.
+    pub fired: Option<u32>,
+}
+
+/// How `select_target` ranks the hostiles within reach.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum TargetMode {
+    Nearest,
+    /// Highest `TargetPriority` first, the nearest among equals.
+    Priority,
+}
+
+impl Default for TargetMode {
+    fn default() -> Self {
+        TargetMode::Nearest
+    }
+}
+
+/// How eagerly AI goes after this character. Characters without one count as 0.
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct TargetPriority(pub i32);
+
+fn default_aggro_radius() -> f32 {
+    100f32
+}
+
+/// The hostile an AI character goes after. Once picked it is kept until it leaves the leash,
+/// stops being hostile or is removed from the world.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Targeting {
+    /// Hostiles closer than this get noticed.
+    #[serde(default = "default_aggro_radius")]
+    pub aggro_radius: f32,
+    /// The current target is dropped beyond this distance, never less than `aggro_radius`.
+    #[serde(default)]
+    pub leash_radius: f32,
+    #[serde(default)]
+    pub mode: TargetMode,
+    #[serde(default)]
+    pub target: Option<EntityID>,
+}
+
+impl Default for Targeting {
+    fn default() -> Self {
+        Self {
+            aggro_radius: default_aggro_radius(),
+            leash_radius: 0f32,
+            mode: TargetMode::default(),
+            target: None,
+        }
+    }
+}
+
+impl Targeting {
+    pub fn leash(&self) -> f32 {
+        self.leash_radius.max(self.aggro_radius)
+    }
+}
+
+fn default_repath_distance() -> f32 {
+    16f32
+}
+
+fn default_waypoint_radius() -> f32 {
+    8f32
+}
+
+/// Walks an AI character around static colliders: `follow_path` takes the goal the AI wrote
+/// into `MoveTarget` and replaces it with the next waypoint toward it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct PathFollower {
+    /// The path is searched again once the goal moved further than this.
+    #[serde(default = "default_repath_distance")]
+    pub repath_distance: f32,
+    /// Waypoints closer than this count as reached.
+    #[serde(default = "default_waypoint_radius")]
+    pub waypoint_radius: f32,
+    /// Goal the current path leads to.
+    #[serde(skip)]
+    pub goal: Option<Vector>,
+    #[serde(skip)]
+    pub waypoints: Vec<Vector>,
+}
+
+impl Default for PathFollower {
+    fn default() -> Self {
+        Self {
+            repath_distance: default_repath_distance(),
+            waypoint_radius: default_waypoint_radius(),
+            goal: None,
+            waypoints: Vec::new(),
+        }
+    }
+}
+
+/// Ranged behaviour: stay between `min_distance` and `max_distance` of the `Targeting`
+/// target, and shoot at it every `cooldown` seconds while in that band.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct KeepDistance {
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub cooldown: f32,
+    #[serde(default)]
+    pub timer: f32,
+}
+
+/// Tuning and state of a character's shield.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Shield {
+    /// Half of the angle covered around the facing, in radians.
+    pub half_arc: f32,
+    /// Fraction of damage and push a block absorbs.
+    pub reduction: f32,
+    /// A hit landing less than this many seconds after the shield went up is parried, so a
+    /// window of 0 never parries.
+    pub parry_window: f32,
+    #[serde(default)]
+    pub raised: bool,
+    /// Seconds since the shield went up.
+    #[serde(default)]
+    pub raised_for: f32,
+}
+
+impl Shield {
+    pub fn update(&mut self, raise: bool, delta: f32) {
+        if raise && self.raised {
+            self.raised_for += delta;
+        } else {
+            self.raised_for = 0f32;
+        }
+        self.raised = raise;
+    }
+    pub fn is_parrying(&self) -> bool {
+        self.raised && self.raised_for < self.parry_window
+    }
+}
+
+/// Arc in front of the character covered by its raised shield.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct ShieldCollider {
+    #[serde(with = "VectorDef")]
+    pub center: Vector,
+    pub radius: f32,
+    pub direction: f32,
+    pub half_arc: f32,
+    pub active: bool,
+}
+
+impl ShieldCollider {
+    /// Whether something at `point` is in front of the shield.
+    pub fn covers(&self, point: Vector) -> bool {
+        if !self.active || point == self.center {
+            return false;
+        }
+        let angle = (point.y - self.center.y).atan2(point.x - self.center.x);
+        let mut diff = (angle - self.direction) % (2f32 * std::f32::consts::PI);
+        if diff > std::f32::consts::PI {
+            diff -= 2f32 * std::f32::consts::PI;
+        } else if diff < -std::f32::consts::PI {
+            diff += 2f32 * std::f32::consts::PI;
+        }
+        diff.abs() <= self.half_arc
+    }
+}
+
+/// Seconds between two ticks of a damage over time effect.
+pub(crate) const DOT_INTERVAL: f32 = 0.5f32;
+/// Poison applications that can run side by side on one character.
+pub(crate) const MAX_POISON_STACKS: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum StatusEffectKind {
+    /// Deals `magnitude` every `DOT_INTERVAL`; each application stacks separately.
+    Poison,
+    /// Multiplies movement speed by `magnitude`; the strongest slow wins.
+    Slow,
+    /// Blocks input and AI.
+    Stun,
+    /// Deals `magnitude` every `DOT_INTERVAL`; a new application refreshes the old one.
+    Burn,
+}
+
+impl StatusEffectKind {
+    pub fn damage_type(self) -> DamageType {
+        match self {
+            StatusEffectKind::Burn => DamageType::Fire,
+            _ => DamageType::Poison,
+        }
+    }
+    fn is_damage_over_time(self) -> bool {
+        self == StatusEffectKind::Poison || self == StatusEffectKind::Burn
+    }
+    /// Tint and how strongly it is mixed into the character color; the strongest one shows.
+    fn tint(self) -> (Color, f32) {
+        match self {
+            StatusEffectKind::Stun => (Color::from_rgba(255, 230, 0, 1f32), 0.7f32),
+            StatusEffectKind::Burn => (Color::from_rgba(255, 100, 0, 1f32), 0.6f32),
+            StatusEffectKind::Poison => (Color::from_rgba(130, 0, 200, 1f32), 0.5f32),
+            StatusEffectKind::Slow => (Color::from_rgba(100, 160, 255, 1f32), 0.4f32),
+        }
+    }
+}
+
+/// An effect as carried by a weapon or a hazard.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct StatusEffect {
+    pub kind: StatusEffectKind,
+    /// Seconds.
+    pub duration: f32,
+    #[serde(default)]
+    pub magnitude: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ActiveEffect {
+    pub kind: StatusEffectKind,
+    pub magnitude: f32,
+    pub remaining: f32,
+    /// Entity credited with damage the effect deals.
+    pub source: EntityID,
+    tick_elapsed: f32,
+}
+
+/// A tick of damage over time, to be turned into a `DamageEvent`.
+pub(crate) struct EffectDamage {
+    pub source: EntityID,
+    pub amount: i32,
+    pub damage_type: DamageType,
+}
+
+/// Effects currently running on a character.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct StatusEffects {
+    pub effects: Vec<ActiveEffect>,
+}
+
+impl StatusEffects {
+    pub fn apply(&mut self, effect: &StatusEffect, source: EntityID) {
+        let active = ActiveEffect {
+            kind: effect.kind,
+            magnitude: effect.magnitude,
+            remaining: effect.duration,
+            source,
+            tick_elapsed: 0f32,
+        };
+        match effect.kind {
+            StatusEffectKind::Poison => {
+                let stacks = self.effects.iter().filter(|e| e.kind == effect.kind).count();
+                if stacks < MAX_POISON_STACKS {
+                    self.effects.push(active);
+                } else if let Some(oldest) = self
+                    .effects
+                    .iter_mut()
+                    .filter(|e| e.kind == effect.kind)
+                    .min_by(|a, b| a.remaining.partial_cmp(&b.remaining).unwrap())
+                {
+                    *oldest = active;
+                }
+            }
+            _ => match self.effects.iter_mut().find(|e| e.kind == effect.kind) {
+                Some(current) => {
+                    current.remaining = current.remaining.max(active.remaining);
+                    current.source = source;
+                    current.magnitude = match effect.kind {
+                        StatusEffectKind::Slow => current.magnitude.min(active.magnitude),
+                        _ => current.magnitude.max(active.magnitude),
+                    };
+                }
+                None => self.effects.push(active),
+            },
+        }
+    }
+
+    /// Counts the effects down, dropping expired ones, and returns the damage they dealt.
+    pub fn update(&mut self, delta: f32) -> Vec<EffectDamage> {
+        let mut damage = Vec::new();
+        for effect in self.effects.iter_mut() {
+            if effect.kind.is_damage_over_time() {
+                effect.tick_elapsed += delta.min(effect.remaining);
+                while effect.tick_elapsed >= DOT_INTERVAL {
+                    effect.tick_elapsed -= DOT_INTERVAL;
+                    damage.push(EffectDamage {
+                        source: effect.source,
+                        amount: effect.magnitude.round() as i32,
+                        damage_type: effect.kind.damage_type(),
+                    });
+                }
+            }
+            effect.remaining -= delta;
+        }
+        self.effects.retain(|e| e.remaining > 0f32);
+        damage
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.effects.iter().any(|e| e.kind == StatusEffectKind::Stun)
+    }
+
+    /// Factor applied to movement speed: zero when stunned, the strongest slow otherwise.
+    pub fn movement_multiplier(&self) -> f32 {
+        if self.is_stunned() {
+            return 0f32;
+        }
+        self.effects
+            .iter()
+            .filter(|e| e.kind == StatusEffectKind::Slow)
+            .map(|e| e.magnitude.max(0f32))
+            .fold(1f32, f32::min)
+    }
+
+    /// `base` mixed with the tint of the most visible effect.
+    pub fn tint(&self, base: Color) -> Color {
+        let strongest = self
+            .effects
+            .iter()
+            .map(|e| e.kind.tint())
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        match strongest {
+            Some((tint, amount)) => Color {
+                r: base.r + (tint.r - base.r) * amount,
+                g: base.g + (tint.g - base.g) * amount,
+                b: base.b + (tint.b - base.b) * amount,
+                a: base.a,
+            },
+            None => base,
+        }
+    }
+}
+
+/// A wall. Characters are pushed out of it, projectiles stop on it and the nav grid routes
+/// around it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct StaticCollider {
+    /// Top left corner.
+    #[serde(with = "VectorDef")]
+    pub position: Vector,
+    #[serde(with = "VectorDef")]
+    pub size: Vector,
+}
+
+impl StaticCollider {
+    pub fn rect(&self) -> Rectangle {
+        Rectangle::new(self.position, self.size)
+    }
+
+    pub fn contains(&self, point: Vector) -> bool {
+        point.x >= self.position.x
+            && point.x <= self.position.x + self.size.x
+            && point.y >= self.position.y
+            && point.y <= self.position.y + self.size.y
+    }
+
+    /// The point of the wall nearest to `point`, which is `point` itself when inside.
+    pub fn closest_point(&self, point: Vector) -> Vector {
+        let max = self.position + self.size;
+        Vector::new(
+            point.x.max(self.position.x).min(max.x),
+            point.y.max(self.position.y).min(max.y),
+        )
+    }
+
+    /// Where a circle at `center` has to move so it no longer overlaps the wall.
+    pub fn push_out(&self, center: Vector, radius: f32) -> Vector {
+        let min = self.position;
+        let max = self.position + self.size;
+        let closest = self.closest_point(center);
+        let offset = center - closest;
+        if offset.len() >= radius {
+            return center;
+        }
+        if offset.len() > 0f32 {
+            return closest + offset.normalize() * radius;
+        }
+        // the center is inside, leave through the nearest side
+        let exits = [
+            (center.x - min.x, Vector::new(min.x - radius, center.y)),
+            (max.x - center.x, Vector::new(max.x + radius, center.y)),
+            (center.y - min.y, Vector::new(center.x, min.y - radius)),
+            (max.y - center.y, Vector::new(center.x, max.y + radius)),
+        ];
+        let mut best = exits[0];
+        for exit in exits.iter().skip(1) {
+            if exit.0 < best.0 {
+                best = *exit;
+            }
+        }
+        best.1
+    }
+}
+
+/// A patch of ground inflicting its effects on every character inside, every `interval`.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Hazard {
+    pub radius: f32,
+    pub effects: Vec<StatusEffect>,
+    pub interval: f32,
+    #[serde(default)]
+    pub timer: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+}
+
+/// One attack of a combo chain.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ComboStep {
+    pub animation: CharacterAnimID,
+    /// Pressing attack during this many last frames of the animation cancels into the next
+    /// step.
+    pub cancel_frames: usize,
+    /// Multiplies the weapon damage of this step.
+    #[serde(default = "ComboStep::default_damage_scale")]
+    pub damage_scale: f32,
+}
+
+impl ComboStep {
+    fn default_damage_scale() -> f32 {
+        1f32
+    }
+}
+
+/// Attack chain of a character. The first step is the plain `CharacterAnimID::Attack`
+/// started by the attack input.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Combo {
+    pub steps: Vec<ComboStep>,
+    #[serde(default)]
+    pub current: usize,
+    /// The attack button was let go since the current step started, so a new press counts.
+    #[serde(default)]
+    pub released: bool,
+}
+
+impl Combo {
+    pub fn step(&self) -> Option<&ComboStep> {
+        self.steps.get(self.current)
+    }
+    pub fn next_step(&self) -> Option<&ComboStep> {
+        self.steps.get(self.current + 1)
+    }
+    pub fn damage_scale(&self) -> f32 {
+        self.step().map_or(1f32, |s| s.damage_scale)
+    }
+    /// Whether `animator` is playing the current step inside its cancel window.
+    pub fn in_cancel_window(&self, animator: &CharacterAnimator) -> bool {
+        match self.step() {
+            Some(step) => {
+                animator.playing_id() == Some(step.animation)
+                    && animator.current_frame() + step.cancel_frames >= animator.frames()
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum PickupEffect {
+    /// Restores health, up to the maximum.
+    Heal(i32),
+    /// Multiplies the damage the collector deals for `duration` seconds.
+    DamageBoost { multiplier: f32, duration: f32 },
+    Coins(u32),
+}
+
+/// An item lying on the ground, taken by the first `Collector` touching it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Pickup {
+    pub effect: PickupEffect,
+}
+
+/// Trigger area of a pickup; it never blocks or hurts anything.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct PickupCollider {
+    #[serde(with = "CircleDef")]
+    pub circle: quicksilver::geom::Circle,
+}
+
+impl PickupCollider {
+    pub fn is_collided(&self, body: &BodyDefenseCollider) -> bool {
+        body.circle.overlaps(&self.circle)
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct PickupView {
+    #[serde(with = "VectorDef")]
+    pub position: Vector,
+    pub radius: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+}
+
+/// Characters that take pickups, and the currency they picked up so far.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Collector {
+    pub coins: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct DamageBoost {
+    pub multiplier: f32,
+    pub remaining: f32,
+}
+
+impl DamageBoost {
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0f32
+    }
+}
+
+/// A pickup left behind on death.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct LootDrop {
+    pub pickup: String,
+    /// Probability in `[0, 1]`.
+    #[serde(default = "LootDrop::default_chance")]
+    pub chance: f32,
+}
+
+impl LootDrop {
+    fn default_chance() -> f32 {
+        1f32
+    }
+}
+
+/// Pickups rolled for when the character dies.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Loot {
+    pub drops: Vec<LootDrop>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::World;
+
+    fn shield(parry_window: f32) -> Shield {
+        Shield {
+            half_arc: 1f32,
+            reduction: 0.5f32,
+            parry_window,
+            raised: false,
+            raised_for: 0f32,
+        }
+    }
+
+    #[test]
+    fn shields_parry_only_inside_the_window() {
+        let mut shield = shield(0.25f32);
+        assert!(!shield.is_parrying());
+        shield.update(true, 0.125f32);
+        assert!(shield.is_parrying());
+        shield.update(true, 0.125f32);
+        assert!(shield.is_parrying());
+        shield.update(true, 0.125f32);
+        assert!(!shield.is_parrying());
+        shield.update(false, 0.125f32);
+        assert!(!shield.is_parrying());
+    }
+
+    #[test]
+    fn zero_window_blocks_without_parrying() {
+        let mut shield = shield(0f32);
+        shield.update(true, 0.125f32);
+        assert!(shield.raised);
+        assert!(!shield.is_parrying());
+    }
+
+    fn effect(kind: StatusEffectKind, duration: f32, magnitude: f32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            duration,
+            magnitude,
+        }
+    }
+
+    fn sources(count: usize) -> Vec<EntityID> {
+        let mut world = World::default();
+        (0..count)
+            .map(|_| add_entity!(world; Position(Vector::ZERO)))
+            .collect()
+    }
+
+    #[test]
+    fn poison_stacks_up_to_the_cap() {
+        let source = sources(1)[0];
+        let mut effects = StatusEffects::default();
+        for duration in 1..=MAX_POISON_STACKS + 2 {
+            effects.apply(
+                &effect(StatusEffectKind::Poison, duration as f32, 1f32),
+                source,
+            );
+        }
+        // past the cap each application replaces the stack closest to running out
+        let mut remaining: Vec<f32> = effects.effects.iter().map(|e| e.remaining).collect();
+        remaining.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected: Vec<f32> = (3..=MAX_POISON_STACKS + 2).map(|d| d as f32).collect();
+        assert_eq!(remaining, expected);
+    }
+
+    #[test]
+    fn burn_refreshes_and_extends() {
+        let ids = sources(2);
+        let mut effects = StatusEffects::default();
+        effects.apply(&effect(StatusEffectKind::Burn, 2f32, 1f32), ids[0]);
+        effects.apply(&effect(StatusEffectKind::Burn, 1f32, 3f32), ids[1]);
+        assert_eq!(effects.effects.len(), 1);
+        let burn = &effects.effects[0];
+        assert_eq!(burn.remaining, 2f32);
+        assert_eq!(burn.magnitude, 3f32);
+        assert_eq!(burn.source, ids[1]);
+
+        effects.apply(&effect(StatusEffectKind::Burn, 4f32, 1f32), ids[0]);
+        assert_eq!(effects.effects.len(), 1);
+        assert_eq!(effects.effects[0].remaining, 4f32);
+        assert_eq!(effects.effects[0].magnitude, 3f32);
+    }
+
+    #[test]
+    fn strongest_slow_wins_and_stun_stops_movement() {
+        let source = sources(1)[0];
+        let mut effects = StatusEffects::default();
+        effects.apply(&effect(StatusEffectKind::Slow, 1f32, 0.5f32), source);
+        effects.apply(&effect(StatusEffectKind::Slow, 1f32, 0.75f32), source);
+        assert_eq!(effects.effects.len(), 1);
+        assert_eq!(effects.movement_multiplier(), 0.5f32);
+        effects.apply(&effect(StatusEffectKind::Stun, 0.5f32, 0f32), source);
+        assert!(effects.is_stunned());
+        assert_eq!(effects.movement_multiplier(), 0f32);
+    }
+
+    #[test]
+    fn damage_over_time_ticks_every_interval() {
+        let source = sources(1)[0];
+        let mut effects = StatusEffects::default();
+        effects.apply(
+            &effect(StatusEffectKind::Poison, 4f32 * DOT_INTERVAL, 2f32),
+            source,
+        );
+        assert!(effects.update(DOT_INTERVAL / 2f32).is_empty());
+        let damage = effects.update(DOT_INTERVAL / 2f32);
+        assert_eq!(damage.len(), 1);
+        assert_eq!(damage[0].amount, 2);
+        assert_eq!(damage[0].source, source);
+        assert_eq!(damage[0].damage_type, DamageType::Poison);
+        // a long frame catches up on every interval it covered
+        assert_eq!(effects.update(2f32 * DOT_INTERVAL).len(), 2);
+        // but never past the end of the effect
+        assert_eq!(effects.update(4f32 * DOT_INTERVAL).len(), 1);
+        assert!(effects.effects.is_empty());
+    }
+}
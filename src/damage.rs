@@ -0,0 +1,155 @@
+use crate::components::*;
+
+/// Turns a hit into the amount of health the victim loses.
+pub(crate) trait DamageFormula {
+    fn compute(&self, weapon: &Weapon, armor: Option<&Armor>) -> i32;
+}
+
+/// Resistance scales the weapon's base damage, then flat armor is subtracted. A hit that
+/// connects always deals at least `minimum`, unless the victim is fully resistant.
+pub(crate) struct StandardFormula {
+    pub minimum: i32,
+}
+
+impl Default for StandardFormula {
+    fn default() -> Self {
+        Self { minimum: 1 }
+    }
+}
+
+impl DamageFormula for StandardFormula {
+    fn compute(&self, weapon: &Weapon, armor: Option<&Armor>) -> i32 {
+        let armor = match armor {
+            Some(armor) => armor,
+            None => return weapon.base_damage,
+        };
+        let resistance = armor.resistance(weapon.damage_type).min(1f32);
+        if resistance >= 1f32 {
+            return 0;
+        }
+        let scaled = (weapon.base_damage as f32 * (1f32 - resistance)).round() as i32;
+        std::cmp::max(scaled - armor.flat_reduction, self.minimum)
+    }
+}
+
+/// The formula in use, replaceable through `Simulation::set_damage_formula`.
+pub(crate) struct DamageModel {
+    formula: Box<dyn DamageFormula>,
+}
+
+impl Default for DamageModel {
+    fn default() -> Self {
+        Self::new(StandardFormula::default())
+    }
+}
+
+impl DamageModel {
+    pub fn new<F: DamageFormula + 'static>(formula: F) -> Self {
+        Self {
+            formula: Box::new(formula),
+        }
+    }
+    pub fn compute(&self, weapon: &Weapon, armor: Option<&Armor>) -> i32 {
+        self.formula.compute(weapon, armor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weapon(base_damage: i32, damage_type: DamageType) -> Weapon {
+        Weapon {
+            base_damage,
+            damage_type,
+            ..Default::default()
+        }
+    }
+
+    fn armor(flat_reduction: i32, resistances: &[(DamageType, f32)]) -> Armor {
+        Armor {
+            flat_reduction,
+            resistances: resistances.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn unarmored_victims_take_base_damage() {
+        let formula = StandardFormula::default();
+        assert_eq!(formula.compute(&weapon(10, DamageType::Slash), None), 10);
+    }
+
+    #[test]
+    fn resistance_scales_only_its_damage_type() {
+        let formula = StandardFormula::default();
+        let armor = armor(0, &[(DamageType::Fire, 0.5f32)]);
+        assert_eq!(
+            formula.compute(&weapon(10, DamageType::Fire), Some(&armor)),
+            5
+        );
+        assert_eq!(
+            formula.compute(&weapon(10, DamageType::Slash), Some(&armor)),
+            10
+        );
+    }
+
+    #[test]
+    fn negative_resistance_is_a_weakness() {
+        let formula = StandardFormula::default();
+        let armor = armor(0, &[(DamageType::Blunt, -0.5f32)]);
+        assert_eq!(
+            formula.compute(&weapon(10, DamageType::Blunt), Some(&armor)),
+            15
+        );
+    }
+
+    #[test]
+    fn flat_armor_applies_after_resistance() {
+        let formula = StandardFormula::default();
+        let armor = armor(3, &[(DamageType::Pierce, 0.5f32)]);
+        assert_eq!(
+            formula.compute(&weapon(20, DamageType::Pierce), Some(&armor)),
+            7
+        );
+    }
+
+    #[test]
+    fn hits_deal_at_least_the_minimum() {
+        let armor = armor(50, &[]);
+        let slash = weapon(10, DamageType::Slash);
+        assert_eq!(StandardFormula::default().compute(&slash, Some(&armor)), 1);
+        assert_eq!(
+            StandardFormula { minimum: 0 }.compute(&slash, Some(&armor)),
+            0
+        );
+    }
+
+    #[test]
+    fn full_resistance_means_immune() {
+        let formula = StandardFormula::default();
+        let poison = weapon(10, DamageType::Poison);
+        assert_eq!(
+            formula.compute(&poison, Some(&armor(0, &[(DamageType::Poison, 1f32)]))),
+            0
+        );
+        assert_eq!(
+            formula.compute(&poison, Some(&armor(0, &[(DamageType::Poison, 2f32)]))),
+            0
+        );
+    }
+
+    struct Halved;
+
+    impl DamageFormula for Halved {
+        fn compute(&self, weapon: &Weapon, _: Option<&Armor>) -> i32 {
+            weapon.base_damage / 2
+        }
+    }
+
+    #[test]
+    fn model_uses_the_installed_formula() {
+        let slash = weapon(10, DamageType::Slash);
+        assert_eq!(DamageModel::default().compute(&slash, None), 10);
+        assert_eq!(DamageModel::new(Halved).compute(&slash, None), 5);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::components::*;
+use crate::*;
+use quicksilver::prelude::*;
+use static_ecs::*;
+
+/// Events of one kind emitted during the current tick. Queues are cleared when the next tick
+/// starts, so anything outside the simulation can still read them after `Simulation::step`.
+#[derive(Clone)]
+pub(crate) struct EventQueue<T> {
+    events: Vec<T>,
+}
+
+impl<T> Default for EventQueue<T> {
+    fn default() -> Self {
+        Self { events: Vec::new() }
+    }
+}
+
+impl<T> EventQueue<T> {
+    pub fn push(&mut self, event: T) {
+        self.events.push(event);
+    }
+    pub fn iter(&self) -> std::slice::Iter<T> {
+        self.events.iter()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<T> {
+        self.events.iter_mut()
+    }
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+    /// Drops the events `f` returns false for, e.g. hits that were blocked.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        self.events.retain(f);
+    }
+}
+
+/// An attack shape touched a hostile body. Turned into a `DamageEvent` by `resolve_hits`.
+#[derive(Clone, Debug)]
+pub(crate) struct HitEvent {
+    pub attacker: EntityID,
+    pub victim: EntityID,
+    /// Multiplies damage and push; lowered by a block.
+    pub scale: f32,
+}
+
+/// A hit landed on a raised shield. A parried hit is dropped and staggers the attacker; a
+/// blocked one goes on with reduced damage.
+#[derive(Clone, Debug)]
+pub(crate) struct BlockEvent {
+    pub attacker: EntityID,
+    pub victim: EntityID,
+    pub parried: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct DamageEvent {
+    pub attacker: EntityID,
+    pub victim: EntityID,
+    pub amount: i32,
+    pub damage_type: DamageType,
+    /// Push on the victim, pointing away from the attacker, in pixels per second.
+    pub impulse: Vector,
+    /// Inflicted on the victim along with the damage.
+    pub effects: Vec<StatusEffect>,
+    /// Dealt by a status effect; does not flinch the victim or make it invulnerable.
+    pub over_time: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct DeathEvent {
+    pub entity: EntityID,
+    /// Attacker of the damage that brought health to zero.
+    pub killer: EntityID,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PickupEvent {
+    pub pickup: EntityID,
+    pub collector: EntityID,
+    pub effect: PickupEffect,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct AnimationFinished {
+    pub entity: EntityID,
+    pub animation: CharacterAnimID,
+}
+
+#[derive(Default, Clone)]
+pub(crate) struct Events {
+    pub hit: EventQueue<HitEvent>,
+    pub block: EventQueue<BlockEvent>,
+    pub damage: EventQueue<DamageEvent>,
+    pub death: EventQueue<DeathEvent>,
+    pub pickup: EventQueue<PickupEvent>,
+    pub animation_finished: EventQueue<AnimationFinished>,
+}
+
+impl Events {
+    pub fn clear(&mut self) {
+        self.hit.clear();
+        self.block.clear();
+        self.damage.clear();
+        self.death.clear();
+        self.pickup.clear();
+        self.animation_finished.clear();
+    }
+}
@@ -0,0 +1,305 @@
+use quicksilver::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Play area covered by the nav grid, the size of the window.
+pub(crate) const NAV_BOUNDS: (f32, f32) = (800f32, 600f32);
+/// Side of a nav cell in pixels.
+pub(crate) const NAV_CELL_SIZE: f32 = 16f32;
+/// How far static colliders are grown before blocking cells, so paths keep characters off
+/// the walls.
+const NAV_CLEARANCE: f32 = 12f32;
+/// The cache is flushed once it holds this many paths.
+const MAX_CACHED_PATHS: usize = 256;
+/// A* step costs, scaled so the diagonal stays an integer.
+const STRAIGHT_COST: i32 = 10;
+const DIAGONAL_COST: i32 = 14;
+
+type Cell = (i32, i32);
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Walkable cells of the play area. A cell is blocked when a static collider, grown by the
+/// clearance, covers its center. Everything outside the grid counts as walkable but can't be
+/// pathed through.
+#[derive(Clone, Default)]
+pub(crate) struct NavGrid {
+    cell_size: f32,
+    columns: i32,
+    rows: i32,
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn build(obstacles: &[Rectangle]) -> Self {
+        let cell_size = NAV_CELL_SIZE;
+        let columns = (NAV_BOUNDS.0 / cell_size).ceil() as i32;
+        let rows = (NAV_BOUNDS.1 / cell_size).ceil() as i32;
+        let mut grid = Self {
+            cell_size,
+            columns,
+            rows,
+            blocked: vec![false; (columns * rows) as usize],
+        };
+        for y in 0..rows {
+            for x in 0..columns {
+                let center = grid.center_of((x, y));
+                grid.blocked[(y * columns + x) as usize] = obstacles.iter().any(|rect| {
+                    center.x >= rect.pos.x - NAV_CLEARANCE
+                        && center.x <= rect.pos.x + rect.size.x + NAV_CLEARANCE
+                        && center.y >= rect.pos.y - NAV_CLEARANCE
+                        && center.y <= rect.pos.y + rect.size.y + NAV_CLEARANCE
+                });
+            }
+        }
+        grid
+    }
+
+    pub fn cell_of(&self, point: Vector) -> Option<Cell> {
+        if self.cell_size <= 0f32 {
+            return None;
+        }
+        let cell = (
+            (point.x / self.cell_size).floor() as i32,
+            (point.y / self.cell_size).floor() as i32,
+        );
+        if self.contains(cell) {
+            Some(cell)
+        } else {
+            None
+        }
+    }
+
+    pub fn center_of(&self, cell: Cell) -> Vector {
+        Vector::new(
+            (cell.0 as f32 + 0.5f32) * self.cell_size,
+            (cell.1 as f32 + 0.5f32) * self.cell_size,
+        )
+    }
+
+    fn contains(&self, cell: Cell) -> bool {
+        cell.0 >= 0 && cell.1 >= 0 && cell.0 < self.columns && cell.1 < self.rows
+    }
+
+    pub fn is_blocked(&self, cell: Cell) -> bool {
+        self.contains(cell) && self.blocked[(cell.1 * self.columns + cell.0) as usize]
+    }
+
+    /// Whether the straight segment from `a` to `b` crosses no blocked cell.
+    pub fn is_clear(&self, a: Vector, b: Vector) -> bool {
+        if self.cell_size <= 0f32 {
+            return true;
+        }
+        let steps = ((b - a).len() / (self.cell_size * 0.5f32)).ceil().max(1f32) as i32;
+        (0..=steps).all(|i| {
+            let point = a + (b - a) * (i as f32 / steps as f32);
+            self.cell_of(point)
+                .map_or(true, |cell| !self.is_blocked(cell))
+        })
+    }
+
+    /// A* over the eight neighbours of each cell, never cutting a blocked corner. Returns the
+    /// cells after `start` up to and including `goal`.
+    pub fn find_cells(&self, start: Cell, goal: Cell) -> Option<Vec<Cell>> {
+        if self.is_blocked(goal) {
+            return None;
+        }
+        let heuristic = |cell: Cell| {
+            let dx = (cell.0 - goal.0).abs();
+            let dy = (cell.1 - goal.1).abs();
+            STRAIGHT_COST * (dx + dy) + (DIAGONAL_COST - 2 * STRAIGHT_COST) * dx.min(dy)
+        };
+        let mut open = BinaryHeap::new();
+        let mut came_from = HashMap::<Cell, Cell>::new();
+        let mut cost = HashMap::<Cell, i32>::new();
+        open.push(Reverse((heuristic(start), start)));
+        cost.insert(start, 0);
+
+        while let Some(Reverse((_, cell))) = open.pop() {
+            if cell == goal {
+                let mut cells = vec![cell];
+                let mut current = cell;
+                while let Some(previous) = came_from.get(&current) {
+                    if *previous == start {
+                        break;
+                    }
+                    cells.push(*previous);
+                    current = *previous;
+                }
+                cells.reverse();
+                return Some(cells);
+            }
+            let cell_cost = cost[&cell];
+            for (dx, dy) in NEIGHBOURS.iter() {
+                let next = (cell.0 + dx, cell.1 + dy);
+                if !self.contains(next) || self.is_blocked(next) {
+                    continue;
+                }
+                let diagonal = *dx != 0 && *dy != 0;
+                if diagonal
+                    && (self.is_blocked((cell.0 + dx, cell.1))
+                        || self.is_blocked((cell.0, cell.1 + dy)))
+                {
+                    continue;
+                }
+                let next_cost = cell_cost
+                    + if diagonal {
+                        DIAGONAL_COST
+                    } else {
+                        STRAIGHT_COST
+                    };
+                if cost.get(&next).map_or(true, |c| next_cost < *c) {
+                    cost.insert(next, next_cost);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((next_cost + heuristic(next), next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The nav grid and the paths already found on it, keyed by start and goal cell.
+#[derive(Default)]
+pub(crate) struct Navigation {
+    grid: NavGrid,
+    /// Hash of the static colliders the grid was built from.
+    signature: u64,
+    cache: HashMap<(Cell, Cell), Option<Vec<Vector>>>,
+}
+
+impl Navigation {
+    pub fn grid(&self) -> &NavGrid {
+        &self.grid
+    }
+
+    pub fn signature(&self) -> u64 {
+        self.signature
+    }
+
+    /// Rebuilds the grid around `obstacles` and forgets every cached path.
+    pub fn rebuild(&mut self, signature: u64, obstacles: &[Rectangle]) {
+        debug!("rebuilding nav grid around {} colliders", obstacles.len());
+        self.grid = NavGrid::build(obstacles);
+        self.signature = signature;
+        self.cache.clear();
+    }
+
+    /// Waypoints from `from` to `to`, the last one being `to` itself. A single waypoint when
+    /// nothing is in the way; `None` when `to` can't be reached.
+    pub fn find_path(&mut self, from: Vector, to: Vector) -> Option<Vec<Vector>> {
+        if self.grid.is_clear(from, to) {
+            return Some(vec![to]);
+        }
+        let key = (self.grid.cell_of(from)?, self.grid.cell_of(to)?);
+        if !self.cache.contains_key(&key) {
+            if self.cache.len() >= MAX_CACHED_PATHS {
+                self.cache.clear();
+            }
+            let path = self
+                .grid
+                .find_cells(key.0, key.1)
+                .map(|cells| self.smooth(key.0, &cells));
+            self.cache.insert(key, path);
+        }
+        let mut path = self.cache[&key].clone()?;
+        path.push(to);
+        Some(path)
+    }
+
+    /// Cell centers where the path from `start` turns, skipping the cells that can be reached
+    /// in a straight line. The goal cell itself is left out; the caller appends the exact goal.
+    fn smooth(&self, start: Cell, cells: &[Cell]) -> Vec<Vector> {
+        let points: Vec<Vector> = cells.iter().map(|c| self.grid.center_of(*c)).collect();
+        let mut corners = Vec::new();
+        let mut anchor = self.grid.center_of(start);
+        for i in 1..points.len() {
+            if !self.grid.is_clear(anchor, points[i]) {
+                anchor = points[i - 1];
+                corners.push(anchor);
+            }
+        }
+        corners
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walled() -> Navigation {
+        let mut navigation = Navigation::default();
+        navigation.rebuild(1, &[Rectangle::new((200f32, 0f32), (16f32, 400f32))]);
+        navigation
+    }
+
+    #[test]
+    fn open_ground_is_a_single_waypoint() {
+        let mut navigation = walled();
+        let to = Vector::new(150f32, 300f32);
+        assert_eq!(
+            navigation.find_path(Vector::new(100f32, 100f32), to),
+            Some(vec![to])
+        );
+    }
+
+    #[test]
+    fn paths_go_around_a_wall() {
+        let mut navigation = walled();
+        // cell centers, where the smoothing measures from
+        let from = Vector::new(104f32, 104f32);
+        let to = Vector::new(296f32, 104f32);
+        assert!(!navigation.grid().is_clear(from, to));
+
+        let path = navigation.find_path(from, to).unwrap();
+        assert!(path.len() > 1);
+        assert_eq!(path.last(), Some(&to));
+        let mut previous = from;
+        for waypoint in path.iter() {
+            assert!(navigation.grid().is_clear(previous, *waypoint));
+            previous = *waypoint;
+        }
+    }
+
+    #[test]
+    fn blocked_goal_has_no_path() {
+        let mut navigation = walled();
+        let goal = Vector::new(208f32, 100f32);
+        assert_eq!(
+            navigation.find_path(Vector::new(100f32, 100f32), goal),
+            None
+        );
+    }
+
+    #[test]
+    fn paths_are_cached_by_cell() {
+        let mut navigation = walled();
+        let from = Vector::new(100f32, 100f32);
+        let to = Vector::new(300f32, 100f32);
+        navigation.find_path(from, to).unwrap();
+        assert_eq!(navigation.cache.len(), 1);
+
+        // a stale entry proves the second search is answered from the cache
+        let key = (
+            navigation.grid().cell_of(from).unwrap(),
+            navigation.grid().cell_of(to).unwrap(),
+        );
+        let corner = Vector::new(1f32, 1f32);
+        navigation.cache.insert(key, Some(vec![corner]));
+        let nearby = from + Vector::new(2f32, 2f32);
+        assert_eq!(navigation.find_path(nearby, to), Some(vec![corner, to]));
+        assert_eq!(navigation.cache.len(), 1);
+
+        navigation.rebuild(2, &[]);
+        assert!(navigation.cache.is_empty());
+    }
+}
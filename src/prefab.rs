@@ -0,0 +1,536 @@
+use crate::animation::*;
+use crate::assets::load_ron_dir;
+use crate::components::*;
+use crate::snapshot::ColorDef;
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Builtin prefabs, see `assets`.
+const BUILTIN_PREFABS: &[(&str, &str)] = &[
+    ("hero", include_str!("../assets/prefabs/hero.ron")),
+    ("enemy_grunt", include_str!("../assets/prefabs/enemy_grunt.ron")),
+    ("enemy_archer", include_str!("../assets/prefabs/enemy_archer.ron")),
+    ("enemy_guard", include_str!("../assets/prefabs/enemy_guard.ron")),
+];
+
+const BUILTIN_PROJECTILES: &[(&str, &str)] =
+    &[("arrow", include_str!("../assets/projectiles/arrow.ron"))];
+
+const BUILTIN_PICKUPS: &[(&str, &str)] = &[
+    ("health_potion", include_str!("../assets/pickups/health_potion.ron")),
+    ("damage_boost", include_str!("../assets/pickups/damage_boost.ron")),
+    ("coin", include_str!("../assets/pickups/coin.ron")),
+];
+
+#[derive(Debug)]
+pub(crate) enum PrefabError {
+    Io(io::Error),
+    Parse(String, ron::Error),
+    Unknown(String),
+    Animation(AnimationError),
+    Behavior(BehaviorError),
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::Io(e) => write!(f, "prefab io error: {}", e),
+            PrefabError::Parse(name, e) => write!(f, "prefab {}: {}", name, e),
+            PrefabError::Unknown(name) => write!(f, "unknown prefab {}", name),
+            PrefabError::Animation(e) => write!(f, "{}", e),
+            PrefabError::Behavior(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for PrefabError {
+    fn from(e: io::Error) -> Self {
+        PrefabError::Io(e)
+    }
+}
+
+impl From<AnimationError> for PrefabError {
+    fn from(e: AnimationError) -> Self {
+        PrefabError::Animation(e)
+    }
+}
+
+impl From<BehaviorError> for PrefabError {
+    fn from(e: BehaviorError) -> Self {
+        PrefabError::Behavior(e)
+    }
+}
+
+fn default_contact_interval() -> f32 {
+    0.5f32
+}
+
+fn default_animations() -> HashMap<CharacterAnimID, String> {
+    let mut animations = HashMap::new();
+    animations.insert(CharacterAnimID::Wait, "wait".to_string());
+    animations.insert(CharacterAnimID::Attack, "attack".to_string());
+    animations.insert(CharacterAnimID::Attack2, "attack2".to_string());
+    animations.insert(CharacterAnimID::Finisher, "finisher".to_string());
+    animations.insert(CharacterAnimID::Damaged, "damaged".to_string());
+    animations.insert(CharacterAnimID::Stagger, "stagger".to_string());
+    animations.insert(CharacterAnimID::Dash, "dash".to_string());
+    animations
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct StatusBarDef {
+    pub length: i32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+}
+
+/// Component bundle of one kind of character, as written in `assets/prefabs/*.ron`.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Prefab {
+    pub team: u32,
+    pub health: i32,
+    pub radius: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+    /// Receives an `Input` component driven by the player.
+    #[serde(default)]
+    pub player_controlled: bool,
+    /// Receives a `MoveTarget` and chases hostile characters.
+    #[serde(default)]
+    pub chase_target: bool,
+    #[serde(default)]
+    pub sword: bool,
+    #[serde(default)]
+    pub body_weapon: bool,
+    #[serde(default)]
+    pub health_bar: Option<StatusBarDef>,
+    #[serde(default)]
+    pub stamina: Option<Stamina>,
+    /// Drawn under the health bar; needs `stamina`.
+    #[serde(default)]
+    pub stamina_bar: Option<StatusBarDef>,
+    #[serde(default)]
+    pub weapon: Option<Weapon>,
+    #[serde(default)]
+    pub armor: Option<Armor>,
+    /// Seconds during which further hits are ignored after taking damage.
+    #[serde(default)]
+    pub invulnerability: Option<f32>,
+    /// Seconds between two hits of a body contact on the same victim.
+    #[serde(default = "default_contact_interval")]
+    pub contact_interval: f32,
+    #[serde(default)]
+    pub mass: Option<Mass>,
+    /// Attack chain, starting with the plain `Attack`.
+    #[serde(default)]
+    pub combo: Option<Vec<ComboStep>>,
+    /// Takes pickups it touches.
+    #[serde(default)]
+    pub collector: bool,
+    /// Pickups that may be left behind on death.
+    #[serde(default)]
+    pub drops: Vec<LootDrop>,
+    /// Raised with the `defend` input.
+    #[serde(default)]
+    pub shield: Option<Shield>,
+    /// Name of the projectile shot on the `fire` frames of the animations.
+    #[serde(default)]
+    pub launcher: Option<String>,
+    /// How AI characters pick what to go after; defaults to the nearest hostile within 100
+    /// pixels.
+    #[serde(default)]
+    pub targeting: Option<Targeting>,
+    /// How eagerly AI goes after this character.
+    #[serde(default)]
+    pub target_priority: Option<i32>,
+    /// Steers toward its `MoveTarget` instead of homing in on it.
+    #[serde(default)]
+    pub steering: Option<Steering>,
+    /// Walks around walls on the way to its `MoveTarget`.
+    #[serde(default)]
+    pub path_follower: Option<PathFollower>,
+    /// Name of the behavior tree driving its `MoveTarget` and buttons.
+    #[serde(default)]
+    pub behavior: Option<String>,
+    /// Receives a `MoveTarget` driven by the enemy state machine.
+    #[serde(default)]
+    pub ai: Option<AiTuning>,
+    /// Receives a `MoveTarget` and keeps a hostile at shooting distance.
+    #[serde(default)]
+    pub keep_distance: Option<KeepDistance>,
+    /// Name in the `AnimationLibrary` of each animation the character can play.
+    #[serde(default = "default_animations")]
+    pub animations: HashMap<CharacterAnimID, String>,
+}
+
+impl Prefab {
+    pub fn animator(&self, library: &AnimationLibrary) -> Result<CharacterAnimator, PrefabError> {
+        let mut animator = CharacterAnimator::default();
+        for (id, name) in self.animations.iter() {
+            animator.register(*id, library.get(name)?.clone());
+        }
+        animator.play(CharacterAnimID::Wait);
+        Ok(animator)
+    }
+
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        library: &AnimationLibrary,
+        behaviors: &BehaviorLibrary,
+        position: Vector,
+    ) -> Result<EntityID, PrefabError> {
+        let animator = self.animator(library)?;
+        if let Some(tree) = &self.behavior {
+            behaviors.get(tree)?;
+        }
+        let entity_id = add_entity!(
+            world;
+            Team::new(self.team),
+            Health::new(self.health),
+            Position(position),
+            BodyDefenseCollider::default(),
+            Direction::default(),
+            Velocity::default(),
+            Knockback::default(),
+            StatusEffects::default(),
+            animator,
+            CharacterView {
+                color: self.color,
+                base_color: self.color,
+                radius: self.radius,
+                radius_scale: 1f32,
+                ..Default::default()
+            },
+        );
+
+        if self.player_controlled {
+            component_mut!(world, Input).insert(entity_id, Input::default());
+        }
+        let ai_driven = self.chase_target
+            || self.ai.is_some()
+            || self.behavior.is_some()
+            || self.keep_distance.is_some();
+        if ai_driven {
+            component_mut!(world, MoveTarget).insert(entity_id, MoveTarget(position));
+            component_mut!(world, AiInput).insert(entity_id, AiInput::default());
+            let targeting = self.targeting.clone().unwrap_or_default();
+            component_mut!(world, Targeting).insert(entity_id, targeting);
+        }
+        if let Some(steering) = &self.steering {
+            component_mut!(world, Steering).insert(entity_id, steering.clone());
+        }
+        if let Some(follower) = &self.path_follower {
+            component_mut!(world, PathFollower).insert(entity_id, follower.clone());
+        }
+        if let Some(priority) = self.target_priority {
+            component_mut!(world, TargetPriority).insert(entity_id, TargetPriority(priority));
+        }
+        if let Some(tree) = &self.behavior {
+            component_mut!(world, Behavior)
+                .insert(entity_id, Behavior::new(tree.clone(), position));
+        }
+        if let Some(tuning) = &self.ai {
+            component_mut!(world, EnemyAi)
+                .insert(entity_id, EnemyAi::new(tuning.clone(), position));
+        }
+        if let Some(keep_distance) = &self.keep_distance {
+            component_mut!(world, KeepDistance).insert(entity_id, keep_distance.clone());
+        }
+        if let Some(projectile) = &self.launcher {
+            component_mut!(world, Launcher).insert(
+                entity_id,
+                Launcher {
+                    projectile: projectile.clone(),
+                    fired: None,
+                },
+            );
+        }
+        if self.sword {
+            component_mut!(world, SwordCollider).insert(entity_id, SwordCollider::default());
+        }
+        if self.body_weapon {
+            component_mut!(world, BodyWeaponCollider)
+                .insert(entity_id, BodyWeaponCollider::default());
+        }
+        if let Some(bar) = &self.health_bar {
+            component_mut!(world, StatusBarView<ForHealth>)
+                .insert(entity_id, StatusBarView::new(bar.length, bar.color));
+        }
+        if let Some(stamina) = &self.stamina {
+            let mut stamina = stamina.clone();
+            stamina.current = stamina.max;
+            component_mut!(world, Stamina).insert(entity_id, stamina);
+            if let Some(bar) = &self.stamina_bar {
+                component_mut!(world, StatusBarView<ForStamina>)
+                    .insert(entity_id, StatusBarView::new(bar.length, bar.color));
+            }
+        }
+        if self.sword || self.body_weapon {
+            let rehit_interval = if self.body_weapon {
+                Some(self.contact_interval)
+            } else {
+                None
+            };
+            component_mut!(world, HitRegistry).insert(entity_id, HitRegistry::new(rehit_interval));
+        }
+        if let Some(duration) = self.invulnerability {
+            component_mut!(world, Invulnerable).insert(entity_id, Invulnerable::new(duration));
+        }
+        if let Some(steps) = &self.combo {
+            component_mut!(world, Combo).insert(
+                entity_id,
+                Combo {
+                    steps: steps.clone(),
+                    ..Default::default()
+                },
+            );
+        }
+        if self.collector {
+            component_mut!(world, Collector).insert(entity_id, Collector::default());
+        }
+        if !self.drops.is_empty() {
+            component_mut!(world, Loot).insert(
+                entity_id,
+                Loot {
+                    drops: self.drops.clone(),
+                },
+            );
+        }
+        if let Some(shield) = &self.shield {
+            component_mut!(world, Shield).insert(entity_id, shield.clone());
+            component_mut!(world, ShieldCollider).insert(entity_id, ShieldCollider::default());
+        }
+        if let Some(mass) = &self.mass {
+            component_mut!(world, Mass).insert(entity_id, mass.clone());
+        }
+        if let Some(weapon) = &self.weapon {
+            component_mut!(world, Weapon).insert(entity_id, weapon.clone());
+        }
+        if let Some(armor) = &self.armor {
+            component_mut!(world, Armor).insert(entity_id, armor.clone());
+        }
+        Ok(entity_id)
+    }
+}
+
+/// A projectile as written in `assets/projectiles/*.ron`.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ProjectilePrefab {
+    pub radius: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+    /// Pixels per second.
+    pub speed: f32,
+    /// Seconds before it disappears.
+    pub lifetime: f32,
+    /// Pixels travelled before it disappears.
+    pub range: f32,
+    pub weapon: Weapon,
+}
+
+impl ProjectilePrefab {
+    /// Spawns the projectile flying along `direction`, on the team of `owner`.
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        owner: EntityID,
+        team: u32,
+        position: Vector,
+        direction: f32,
+    ) -> EntityID {
+        let velocity = Vector::new(direction.cos(), direction.sin()) * self.speed;
+        add_entity!(
+            world;
+            Projectile {
+                owner,
+                lifetime: self.lifetime,
+                range: self.range,
+            },
+            Team::new(team),
+            Position(position),
+            Velocity(velocity),
+            self.weapon.clone(),
+            ProjectileCollider {
+                circle: Circle::new(position, self.radius),
+            },
+            ProjectileView {
+                position,
+                radius: self.radius,
+                color: self.color,
+            },
+        )
+    }
+}
+
+/// A pickup as written in `assets/pickups/*.ron`.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct PickupPrefab {
+    pub radius: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+    pub effect: PickupEffect,
+}
+
+impl PickupPrefab {
+    pub fn spawn(&self, world: &mut World, position: Vector) -> EntityID {
+        add_entity!(
+            world;
+            Pickup {
+                effect: self.effect.clone(),
+            },
+            Position(position),
+            PickupCollider {
+                circle: Circle::new(position, self.radius),
+            },
+            PickupView {
+                position,
+                radius: self.radius,
+                color: self.color,
+            },
+        )
+    }
+}
+
+#[derive(Default, Clone)]
+pub(crate) struct PrefabRegistry {
+    prefabs: HashMap<String, Prefab>,
+    projectiles: HashMap<String, ProjectilePrefab>,
+    pickups: HashMap<String, PickupPrefab>,
+    animations: AnimationLibrary,
+    behaviors: BehaviorLibrary,
+}
+
+impl PrefabRegistry {
+    pub fn builtin() -> Self {
+        let mut registry = Self {
+            animations: AnimationLibrary::builtin(),
+            behaviors: BehaviorLibrary::builtin(),
+            ..Default::default()
+        };
+        for (name, source) in BUILTIN_PREFABS {
+            registry
+                .load_str(name, source)
+                .expect("builtin prefabs must parse");
+        }
+        for (name, source) in BUILTIN_PROJECTILES {
+            registry
+                .load_projectile_str(name, source)
+                .expect("builtin projectiles must parse");
+        }
+        for (name, source) in BUILTIN_PICKUPS {
+            registry
+                .load_pickup_str(name, source)
+                .expect("builtin pickups must parse");
+        }
+        registry
+    }
+
+    pub fn load_str(&mut self, name: &str, source: &str) -> Result<(), PrefabError> {
+        let prefab =
+            ron::de::from_str(source).map_err(|e| PrefabError::Parse(name.to_string(), e))?;
+        self.prefabs.insert(name.to_string(), prefab);
+        Ok(())
+    }
+
+    /// Loads every `*.ron` file in `dir`, named after the file stem. Existing prefabs of the
+    /// same name are replaced, so a directory can override the builtin ones.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), PrefabError> {
+        load_ron_dir(dir, |name, source| self.load_str(name, source))
+    }
+
+    pub fn load_projectile_str(&mut self, name: &str, source: &str) -> Result<(), PrefabError> {
+        let projectile =
+            ron::de::from_str(source).map_err(|e| PrefabError::Parse(name.to_string(), e))?;
+        self.projectiles.insert(name.to_string(), projectile);
+        Ok(())
+    }
+
+    /// Like `load_dir`, for projectiles.
+    pub fn load_projectile_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), PrefabError> {
+        load_ron_dir(dir, |name, source| self.load_projectile_str(name, source))
+    }
+
+    pub fn load_pickup_str(&mut self, name: &str, source: &str) -> Result<(), PrefabError> {
+        let pickup =
+            ron::de::from_str(source).map_err(|e| PrefabError::Parse(name.to_string(), e))?;
+        self.pickups.insert(name.to_string(), pickup);
+        Ok(())
+    }
+
+    /// Like `load_dir`, for pickups.
+    pub fn load_pickup_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), PrefabError> {
+        load_ron_dir(dir, |name, source| self.load_pickup_str(name, source))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn projectile(&self, name: &str) -> Option<&ProjectilePrefab> {
+        self.projectiles.get(name)
+    }
+
+    pub fn pickup(&self, name: &str) -> Option<&PickupPrefab> {
+        self.pickups.get(name)
+    }
+
+    pub fn animations(&self) -> &AnimationLibrary {
+        &self.animations
+    }
+
+    pub fn animations_mut(&mut self) -> &mut AnimationLibrary {
+        &mut self.animations
+    }
+
+    pub fn behaviors(&self) -> &BehaviorLibrary {
+        &self.behaviors
+    }
+
+    pub fn behaviors_mut(&mut self) -> &mut BehaviorLibrary {
+        &mut self.behaviors
+    }
+
+    pub fn spawn_prefab(
+        &self,
+        world: &mut World,
+        name: &str,
+        position: Vector,
+    ) -> Result<EntityID, PrefabError> {
+        let prefab = self
+            .get(name)
+            .ok_or_else(|| PrefabError::Unknown(name.to_string()))?;
+        prefab.spawn(world, &self.animations, &self.behaviors, position)
+    }
+
+    pub fn spawn_projectile(
+        &self,
+        world: &mut World,
+        name: &str,
+        owner: EntityID,
+        team: u32,
+        position: Vector,
+        direction: f32,
+    ) -> Result<EntityID, PrefabError> {
+        let projectile = self
+            .projectile(name)
+            .ok_or_else(|| PrefabError::Unknown(name.to_string()))?;
+        Ok(projectile.spawn(world, owner, team, position, direction))
+    }
+
+    pub fn spawn_pickup(
+        &self,
+        world: &mut World,
+        name: &str,
+        position: Vector,
+    ) -> Result<EntityID, PrefabError> {
+        let pickup = self
+            .pickup(name)
+            .ok_or_else(|| PrefabError::Unknown(name.to_string()))?;
+        Ok(pickup.spawn(world, position))
+    }
+}
@@ -0,0 +1,252 @@
+use crate::components::*;
+use crate::simulation::*;
+use crate::snapshot::*;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RPLY";
+const VERSION: u8 = 2;
+
+#[derive(Debug)]
+pub(crate) enum ReplayError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Snapshot(SnapshotError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "replay io error: {}", e),
+            ReplayError::BadMagic => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(v) => write!(f, "unsupported replay version {}", v),
+            ReplayError::Truncated => write!(f, "replay file is truncated"),
+            ReplayError::Snapshot(e) => write!(f, "replay start state: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<SnapshotError> for ReplayError {
+    fn from(e: SnapshotError) -> Self {
+        ReplayError::Snapshot(e)
+    }
+}
+
+/// Collects the `Input` of every tick while a session is played.
+#[derive(Clone)]
+pub(crate) struct ReplayRecorder {
+    start: SimulationSnapshot,
+    inputs: Vec<u8>,
+}
+
+impl ReplayRecorder {
+    pub fn new(start: SimulationSnapshot) -> Self {
+        Self {
+            start,
+            inputs: Vec::new(),
+        }
+    }
+    pub fn record(&mut self, input: &Input) {
+        self.inputs.push(input.to_bits());
+    }
+    pub fn finish(self, checksum: u64) -> Replay {
+        Replay {
+            start: self.start,
+            inputs: self.inputs,
+            checksum,
+        }
+    }
+}
+
+/// The state a session started from, its per-tick inputs and the `Simulation::checksum` it
+/// ended with.
+///
+/// On disk the start state is a length-prefixed `SimulationSnapshot` and the inputs are
+/// run-length encoded as `(bits: u8, run: u16)` pairs, since buttons are usually held for many
+/// ticks.
+#[derive(Default, Clone)]
+pub(crate) struct Replay {
+    start: SimulationSnapshot,
+    inputs: Vec<u8>,
+    checksum: u64,
+}
+
+impl Replay {
+    pub fn ticks(&self) -> usize {
+        self.inputs.len()
+    }
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+    pub fn inputs(&self) -> impl Iterator<Item = Input> + '_ {
+        self.inputs.iter().map(|bits| Input::from_bits(*bits))
+    }
+
+    /// Restores the start state on `simulation`, replays every tick and returns its checksum
+    /// afterwards.
+    pub fn play(&self, simulation: &mut Simulation) -> Result<u64, ReplayError> {
+        simulation.restore(&self.start)?;
+        self.inputs().for_each(|input| simulation.step(&input));
+        Ok(simulation.checksum())
+    }
+
+    /// Replays on `simulation` and tells whether it ends in the recorded state. The simulation
+    /// needs the prefabs the session was recorded with, since spawns during the replay use them.
+    pub fn verify(&self, simulation: &mut Simulation) -> Result<bool, ReplayError> {
+        Ok(self.play(simulation)? == self.checksum)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ReplayError> {
+        let start = self.start.to_bytes()?;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(start.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&start);
+        bytes.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+
+        let mut iter = self.inputs.iter().peekable();
+        while let Some(bits) = iter.next() {
+            let mut run: u16 = 1;
+            while run < u16::max_value() && iter.peek() == Some(&bits) {
+                iter.next();
+                run += 1;
+            }
+            bytes.push(*bits);
+            bytes.extend_from_slice(&run.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.len() < MAGIC.len() + 1 {
+            return Err(ReplayError::Truncated);
+        }
+        if &bytes[0..4] != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(ReplayError::UnsupportedVersion(bytes[4]));
+        }
+        let (start_len, rest) = read_u32(&bytes[5..])?;
+        if rest.len() < start_len {
+            return Err(ReplayError::Truncated);
+        }
+        let (start, rest) = rest.split_at(start_len);
+        let start = SimulationSnapshot::from_bytes(start)?;
+
+        let (ticks, rest) = read_u32(rest)?;
+        if rest.len() < 8 || (rest.len() - 8) % 3 != 0 {
+            return Err(ReplayError::Truncated);
+        }
+        let (runs, tail) = rest.split_at(rest.len() - 8);
+        let count = |run: &[u8]| u16::from_le_bytes([run[1], run[2]]) as usize;
+        if runs.chunks(3).map(count).sum::<usize>() != ticks {
+            return Err(ReplayError::Truncated);
+        }
+        let mut inputs = Vec::with_capacity(ticks);
+        runs.chunks(3).for_each(|run| {
+            inputs.extend(std::iter::repeat(run[0]).take(count(run)));
+        });
+
+        let mut checksum = [0u8; 8];
+        checksum.copy_from_slice(tail);
+        Ok(Self {
+            start,
+            inputs,
+            checksum: u64::from_le_bytes(checksum),
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+/// Splits a little-endian `u32` off the front of `bytes`.
+fn read_u32(bytes: &[u8]) -> Result<(usize, &[u8]), ReplayError> {
+    if bytes.len() < 4 {
+        return Err(ReplayError::Truncated);
+    }
+    let mut value = [0u8; 4];
+    value.copy_from_slice(&bytes[0..4]);
+    Ok((u32::from_le_bytes(value) as usize, &bytes[4..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_ticks(simulation: &mut Simulation, ticks: u32) {
+        for tick in 0..ticks {
+            let input = Input {
+                right: tick % 90 < 45,
+                down: tick % 60 < 20,
+                attack: tick % 40 == 0,
+                ..Default::default()
+            };
+            simulation.step(&input);
+        }
+    }
+
+    #[test]
+    fn replay_round_trips_and_reproduces_the_session() {
+        let mut simulation = Simulation::new();
+        simulation.start_recording();
+        play_ticks(&mut simulation, 240);
+        let replay = simulation.finish_recording().unwrap();
+        assert_eq!(replay.ticks(), 240);
+
+        let loaded = Replay::from_bytes(&replay.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.ticks(), replay.ticks());
+        assert_eq!(loaded.checksum(), replay.checksum());
+        assert_eq!(
+            loaded.play(&mut Simulation::new()).unwrap(),
+            replay.checksum()
+        );
+    }
+
+    #[test]
+    fn later_recordings_replay_from_where_they_started() {
+        let mut simulation = Simulation::new();
+        simulation.start_recording();
+        play_ticks(&mut simulation, 120);
+        simulation.finish_recording().unwrap();
+        simulation.start_recording();
+        play_ticks(&mut simulation, 120);
+        let replay = simulation.finish_recording().unwrap();
+        assert!(replay.verify(&mut Simulation::new()).unwrap());
+    }
+
+    #[test]
+    fn tick_count_must_match_the_runs() {
+        let mut simulation = Simulation::new();
+        simulation.start_recording();
+        play_ticks(&mut simulation, 10);
+        let replay = simulation.finish_recording().unwrap();
+        let mut bytes = replay.to_bytes().unwrap();
+        let start_len = replay.start.to_bytes().unwrap().len();
+        let ticks = 5 + 4 + start_len;
+        bytes[ticks..ticks + 4].copy_from_slice(&u32::max_value().to_le_bytes());
+        match Replay::from_bytes(&bytes) {
+            Err(ReplayError::Truncated) => {}
+            _ => panic!("expected a truncated replay"),
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use static_ecs::*;
+
+use crate::commands::*;
+use crate::components::*;
+use crate::damage::*;
+use crate::events::*;
+use crate::navigation::*;
+use crate::prefab::*;
+use crate::replay::*;
+use crate::snapshot::*;
+use crate::spatial::*;
+use crate::systems::*;
+use crate::teams::*;
+use crate::time::*;
+use crate::*;
+use quicksilver::prelude::*;
+use std::hash::Hasher;
+
+/// Anything in the attack broadphase that can hurt a `BodyDefenseCollider`.
+#[derive(Clone, Copy)]
+pub(crate) enum AttackShape {
+    Sword(EntityID),
+    Body(EntityID),
+    Projectile(EntityID),
+}
+
+impl AttackShape {
+    pub fn entity_id(&self) -> EntityID {
+        match self {
+            AttackShape::Sword(id) | AttackShape::Body(id) | AttackShape::Projectile(id) => *id,
+        }
+    }
+}
+
+/// Broadphase grids rebuilt every tick by the `index_*` systems.
+#[derive(Default)]
+pub(crate) struct SpatialIndex {
+    pub characters: SpatialGrid<EntityID>,
+    pub attacks: SpatialGrid<AttackShape>,
+}
+
+/// State outside the `World` that systems share during a tick.
+#[derive(Default)]
+pub(crate) struct Resources {
+    pub time: Time,
+    /// Player input of the current tick.
+    pub input: Input,
+    pub events: Events,
+    pub commands: CommandBuffer,
+    pub prefabs: PrefabRegistry,
+    pub spatial: SpatialIndex,
+    pub damage_model: DamageModel,
+    pub teams: TeamRelations,
+    pub navigation: Navigation,
+}
+
+impl Resources {
+    pub fn apply_commands(&mut self, world: &mut World) {
+        self.commands.apply(world, &self.prefabs);
+    }
+}
+
+/// FNV-1a. Unlike `DefaultHasher` it hashes the same on every platform and Rust release, so
+/// anything a replay has to reproduce can depend on it. Integers are fed as little endian,
+/// `usize` as 64 bits.
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Owns the `World` and runs the gameplay systems without any window.
+pub(crate) struct Simulation {
+    world: World,
+    resources: Resources,
+    scheduler: Scheduler,
+    timestep: FixedTimestep,
+    recorder: Option<ReplayRecorder>,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self::with_prefabs(PrefabRegistry::builtin())
+    }
+
+    /// Starts the default stage, spawning characters from `prefabs`.
+    pub fn with_prefabs(prefabs: PrefabRegistry) -> Self {
+        let mut simulation = Self::with_world(World::default());
+        simulation.resources.prefabs = prefabs;
+        let stage = [
+            ("hero", 150f32, 150f32),
+            ("enemy_grunt", 20f32, 20f32),
+            ("enemy_grunt", 100f32, 20f32),
+            ("enemy_archer", 400f32, 300f32),
+            ("enemy_guard", 520f32, 140f32),
+        ];
+        for (name, x, y) in stage.iter() {
+            if let Err(e) = simulation.spawn_prefab(name, Vector::new(*x, *y)) {
+                error!("{}", e);
+            }
+        }
+        let fire = Hazard {
+            radius: 30f32,
+            effects: vec![StatusEffect {
+                kind: StatusEffectKind::Burn,
+                duration: 2f32,
+                magnitude: 2f32,
+            }],
+            interval: 0.5f32,
+            timer: 0f32,
+            color: Color::from_rgba(255, 100, 0, 1f32),
+        };
+        simulation.spawn_hazard(fire, Vector::new(300f32, 200f32));
+        let walls = [
+            (40f32, 70f32, 140f32, 16f32),
+            (250f32, 230f32, 16f32, 120f32),
+        ];
+        for (x, y, width, height) in walls.iter() {
+            simulation.spawn_wall(Vector::new(*x, *y), Vector::new(*width, *height));
+        }
+        simulation
+    }
+
+    pub fn with_world(world: World) -> Self {
+        Self {
+            world,
+            resources: Resources {
+                prefabs: PrefabRegistry::builtin(),
+                teams: TeamRelations::builtin(),
+                ..Default::default()
+            },
+            scheduler: default_scheduler(),
+            timestep: FixedTimestep::default(),
+            recorder: None,
+        }
+    }
+
+    pub fn prefabs(&self) -> &PrefabRegistry {
+        &self.resources.prefabs
+    }
+
+    pub fn spawn_prefab(&mut self, name: &str, position: Vector) -> Result<EntityID, PrefabError> {
+        self.resources
+            .prefabs
+            .spawn_prefab(&mut self.world, name, position)
+    }
+
+    pub fn spawn_hazard(&mut self, hazard: Hazard, position: Vector) -> EntityID {
+        add_entity!(self.world; Position(position), hazard)
+    }
+
+    pub fn spawn_wall(&mut self, position: Vector, size: Vector) -> EntityID {
+        add_entity!(self.world; StaticCollider { position, size })
+    }
+
+    /// Path from `from` to `to` around the walls, as of the last tick. See
+    /// `Navigation::find_path`.
+    pub fn find_path(&mut self, from: Vector, to: Vector) -> Option<Vec<Vector>> {
+        self.resources.navigation.find_path(from, to)
+    }
+
+    /// Commands queued here are applied at the start of the next tick.
+    pub fn commands(&mut self) -> &mut CommandBuffer {
+        &mut self.resources.commands
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.resources.time
+    }
+
+    pub fn set_damage_formula<F: DamageFormula + 'static>(&mut self, formula: F) {
+        self.resources.damage_model = DamageModel::new(formula);
+    }
+
+    pub fn teams(&self) -> &TeamRelations {
+        &self.resources.teams
+    }
+
+    pub fn teams_mut(&mut self) -> &mut TeamRelations {
+        &mut self.resources.teams
+    }
+
+    /// Events emitted during the last tick.
+    pub fn events(&self) -> &Events {
+        &self.resources.events
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Add, disable or reorder systems here.
+    pub fn scheduler_mut(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
+    }
+
+    /// World, time and team relations as of the last tick. Queued commands are applied first,
+    /// since they can't be stored.
+    pub fn snapshot(&mut self) -> SimulationSnapshot {
+        self.resources.apply_commands(&mut self.world);
+        let mut world = self.world.save();
+        world.last_id = world.last_id.max(self.resources.commands.retired());
+        SimulationSnapshot {
+            world,
+            time: self.resources.time.clone(),
+            teams: self.resources.teams.clone(),
+        }
+    }
+
+    /// Continues from `snapshot`, dropping queued commands and the events of the last tick.
+    /// Stops recording, since a replay can't follow the jump.
+    pub fn restore(&mut self, snapshot: &SimulationSnapshot) -> Result<(), SnapshotError> {
+        self.world = World::load(&snapshot.world)?;
+        self.resources.time = snapshot.time.clone();
+        self.resources.teams = snapshot.teams.clone();
+        self.resources.commands = CommandBuffer::default();
+        self.resources.commands.retire(snapshot.world.last_id);
+        self.resources.events.clear();
+        self.recorder = None;
+        Ok(())
+    }
+
+    /// Starts recording the input of every following tick, from the state as of the last one.
+    /// The world is reloaded from that state, so the session and its replays go on from
+    /// identical worlds.
+    pub fn start_recording(&mut self) {
+        let start = self.snapshot();
+        if let Err(e) = self.restore(&start) {
+            error!("{}", e);
+        }
+        self.recorder = Some(ReplayRecorder::new(start));
+    }
+
+    /// Stops recording and returns the replay, stamped with the current checksum.
+    pub fn finish_recording(&mut self) -> Option<Replay> {
+        let checksum = self.checksum();
+        self.recorder.take().map(|r| r.finish(checksum))
+    }
+
+    /// `Fnv` hash over the binary snapshot of the world and the tick, used to check that a
+    /// replay reproduced a session.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = Fnv::default();
+        hasher.write_u64(self.resources.time.tick());
+        match self.world.save().to_bytes() {
+            Ok(bytes) => hasher.write(&bytes),
+            Err(e) => error!("{}", e),
+        }
+        hasher.finish()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Feeds `frame_delta` seconds of wall time into the fixed timestep and runs as many ticks
+    /// as fit. Returns the number of ticks run.
+    pub fn advance(&mut self, frame_delta: f32, input: &Input) -> u32 {
+        let steps = self.timestep.accumulate(frame_delta);
+        for _ in 0..steps {
+            self.step(input);
+        }
+        steps
+    }
+
+    /// Advances the world by one fixed tick. `input` is copied into every `Input` component first.
+    pub fn step(&mut self, input: &Input) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(input);
+        }
+        self.resources.time.advance(self.timestep.step());
+        self.resources.input = input.clone();
+        self.resources.events.clear();
+        self.resources.apply_commands(&mut self.world);
+        if let Err(e) = self.scheduler.run(&mut self.world, &mut self.resources) {
+            error!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv_matches_reference_hashes() {
+        assert_eq!(Fnv::default().finish(), 0xcbf2_9ce4_8422_2325);
+        let mut hasher = Fnv::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn restored_world_does_not_reuse_despawned_ids() {
+        let mut simulation = Simulation::with_world(World::default());
+        let size = Vector::new(10f32, 10f32);
+        let ids: Vec<EntityID> = (0..3)
+            .map(|i| simulation.spawn_wall(Vector::new(i as f32 * 20f32, 0f32), size))
+            .collect();
+        simulation.resources.commands.despawn(ids[2]);
+
+        // twice, so the second snapshot has to remember ids despawned before the first
+        for _ in 0..2 {
+            let snapshot = simulation.snapshot();
+            assert_eq!(snapshot.world.last_id, Some(ids[2]));
+            simulation.restore(&snapshot).unwrap();
+        }
+        let id = simulation.spawn_wall(Vector::ZERO, size);
+        assert!(id > ids[2]);
+    }
+}
@@ -0,0 +1,404 @@
+use crate::components::*;
+use crate::teams::TeamRelations;
+use crate::time::Time;
+use crate::*;
+use quicksilver::geom::{Circle, Line};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Bumped whenever a component changes shape; `World::load` refuses other versions.
+pub(crate) const SNAPSHOT_VERSION: u32 = 18;
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Vector")]
+pub(crate) struct VectorDef {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Color")]
+pub(crate) struct ColorDef {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Circle")]
+pub(crate) struct CircleDef {
+    #[serde(with = "VectorDef")]
+    pub pos: Vector,
+    pub radius: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Line")]
+pub(crate) struct LineDef {
+    #[serde(with = "VectorDef")]
+    pub a: Vector,
+    #[serde(with = "VectorDef")]
+    pub b: Vector,
+    pub t: f32,
+}
+
+#[derive(Debug)]
+pub(crate) enum SnapshotError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {}", e),
+            SnapshotError::Json(e) => write!(f, "snapshot json error: {}", e),
+            SnapshotError::Binary(e) => write!(f, "snapshot binary error: {}", e),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {}", v)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Json(e)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self {
+        SnapshotError::Binary(e)
+    }
+}
+
+/// All components of one entity; absent components are `None`.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct EntitySnapshot {
+    pub id: EntityID,
+    pub input: Option<Input>,
+    pub team: Option<Team>,
+    pub health: Option<Health>,
+    pub sword_collider: Option<SwordCollider>,
+    pub body_weapon_collider: Option<BodyWeaponCollider>,
+    pub body_defense_collider: Option<BodyDefenseCollider>,
+    pub move_target: Option<MoveTarget>,
+    pub position: Option<Position>,
+    pub direction: Option<Direction>,
+    pub velocity: Option<Velocity>,
+    pub character_animator: Option<CharacterAnimator>,
+    pub health_bar: Option<StatusBarView<ForHealth>>,
+    pub character_view: Option<CharacterView>,
+    pub weapon: Option<Weapon>,
+    pub armor: Option<Armor>,
+    pub hit_registry: Option<HitRegistry>,
+    pub invulnerable: Option<Invulnerable>,
+    pub mass: Option<Mass>,
+    pub knockback: Option<Knockback>,
+    pub projectile: Option<Projectile>,
+    pub projectile_collider: Option<ProjectileCollider>,
+    pub projectile_view: Option<ProjectileView>,
+    pub launcher: Option<Launcher>,
+    pub keep_distance: Option<KeepDistance>,
+    pub shield: Option<Shield>,
+    pub shield_collider: Option<ShieldCollider>,
+    pub stamina: Option<Stamina>,
+    pub stamina_bar: Option<StatusBarView<ForStamina>>,
+    pub status_effects: Option<StatusEffects>,
+    pub hazard: Option<Hazard>,
+    pub combo: Option<Combo>,
+    pub pickup: Option<Pickup>,
+    pub pickup_collider: Option<PickupCollider>,
+    pub pickup_view: Option<PickupView>,
+    pub collector: Option<Collector>,
+    pub damage_boost: Option<DamageBoost>,
+    pub loot: Option<Loot>,
+    pub enemy_ai: Option<EnemyAi>,
+    pub targeting: Option<Targeting>,
+    pub target_priority: Option<TargetPriority>,
+    pub static_collider: Option<StaticCollider>,
+    pub path_follower: Option<PathFollower>,
+    pub steering: Option<Steering>,
+    pub behavior: Option<Behavior>,
+    pub ai_input: Option<AiInput>,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct WorldSnapshot {
+    pub version: u32,
+    /// Highest id the world has handed out, despawned entities included. `World::load`
+    /// never hands it out again.
+    pub last_id: Option<EntityID>,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+impl WorldSnapshot {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        Self::checked(serde_json::from_str(json)?)
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        Ok(bincode::serialize(self)?)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        Self::checked(bincode::deserialize(bytes)?)
+    }
+
+    /// Writes JSON when `path` ends in `.json`, the binary format otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        if is_json(path.as_ref()) {
+            std::fs::write(path, self.to_json()?)?;
+        } else {
+            std::fs::write(path, self.to_bytes()?)?;
+        }
+        Ok(())
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        if is_json(path.as_ref()) {
+            Self::from_json(&std::fs::read_to_string(path)?)
+        } else {
+            Self::from_bytes(&std::fs::read(path)?)
+        }
+    }
+
+    fn checked(snapshot: Self) -> Result<Self, SnapshotError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// A `WorldSnapshot` plus the resources the following ticks depend on, enough to continue a
+/// session from where it was taken.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct SimulationSnapshot {
+    pub world: WorldSnapshot,
+    pub time: Time,
+    pub teams: TeamRelations,
+}
+
+impl SimulationSnapshot {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        Self::checked(serde_json::from_str(json)?)
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        Ok(bincode::serialize(self)?)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        Self::checked(bincode::deserialize(bytes)?)
+    }
+
+    /// Writes JSON when `path` ends in `.json`, the binary format otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        if is_json(path.as_ref()) {
+            std::fs::write(path, self.to_json()?)?;
+        } else {
+            std::fs::write(path, self.to_bytes()?)?;
+        }
+        Ok(())
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        if is_json(path.as_ref()) {
+            Self::from_json(&std::fs::read_to_string(path)?)
+        } else {
+            Self::from_bytes(&std::fs::read(path)?)
+        }
+    }
+
+    fn checked(snapshot: Self) -> Result<Self, SnapshotError> {
+        if snapshot.world.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.world.version));
+        }
+        Ok(snapshot)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "json")
+}
+
+impl World {
+    /// `last_id` is the highest live id; callers that despawned entities raise it, as
+    /// `Simulation::snapshot` does.
+    pub fn save(&self) -> WorldSnapshot {
+        let mut entities = BTreeMap::<EntityID, EntitySnapshot>::new();
+
+        macro_rules! collect {
+            ($field:ident, $t:ty) => {
+                component!(self, $t).iter().for_each(|(entity_id, c)| {
+                    entities
+                        .entry(entity_id)
+                        .or_insert_with(|| EntitySnapshot {
+                            id: entity_id,
+                            ..Default::default()
+                        })
+                        .$field = Some(c.clone());
+                });
+            };
+        }
+
+        collect!(input, Input);
+        collect!(team, Team);
+        collect!(health, Health);
+        collect!(sword_collider, SwordCollider);
+        collect!(body_weapon_collider, BodyWeaponCollider);
+        collect!(body_defense_collider, BodyDefenseCollider);
+        collect!(move_target, MoveTarget);
+        collect!(position, Position);
+        collect!(direction, Direction);
+        collect!(velocity, Velocity);
+        collect!(character_animator, CharacterAnimator);
+        collect!(health_bar, StatusBarView<ForHealth>);
+        collect!(character_view, CharacterView);
+        collect!(weapon, Weapon);
+        collect!(armor, Armor);
+        collect!(hit_registry, HitRegistry);
+        collect!(invulnerable, Invulnerable);
+        collect!(mass, Mass);
+        collect!(knockback, Knockback);
+        collect!(projectile, Projectile);
+        collect!(projectile_collider, ProjectileCollider);
+        collect!(projectile_view, ProjectileView);
+        collect!(launcher, Launcher);
+        collect!(keep_distance, KeepDistance);
+        collect!(shield, Shield);
+        collect!(shield_collider, ShieldCollider);
+        collect!(stamina, Stamina);
+        collect!(stamina_bar, StatusBarView<ForStamina>);
+        collect!(status_effects, StatusEffects);
+        collect!(hazard, Hazard);
+        collect!(combo, Combo);
+        collect!(pickup, Pickup);
+        collect!(pickup_collider, PickupCollider);
+        collect!(pickup_view, PickupView);
+        collect!(collector, Collector);
+        collect!(damage_boost, DamageBoost);
+        collect!(loot, Loot);
+        collect!(enemy_ai, EnemyAi);
+        collect!(targeting, Targeting);
+        collect!(target_priority, TargetPriority);
+        collect!(static_collider, StaticCollider);
+        collect!(path_follower, PathFollower);
+        collect!(steering, Steering);
+        collect!(behavior, Behavior);
+        collect!(ai_input, AiInput);
+
+        WorldSnapshot {
+            version: SNAPSHOT_VERSION,
+            last_id: entities.keys().next_back().cloned(),
+            entities: entities.into_iter().map(|(_, e)| e).collect(),
+        }
+    }
+
+    pub fn load(snapshot: &WorldSnapshot) -> Result<World, SnapshotError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        let mut world = World::default();
+
+        // `add_entity!` takes ids from a counter the world doesn't expose. Throwaway entities
+        // advance it past `last_id`, so entities spawned later never reuse an id.
+        if let Some(last) = snapshot.last_id {
+            loop {
+                let id = add_entity!(world; Position(Vector::ZERO));
+                world.remove_component(id);
+                if id >= last {
+                    break;
+                }
+            }
+        }
+
+        macro_rules! restore {
+            ($entity:ident, $field:ident, $t:ty) => {
+                if let Some(c) = &$entity.$field {
+                    component_mut!(world, $t).insert($entity.id, c.clone());
+                }
+            };
+        }
+
+        for e in snapshot.entities.iter() {
+            restore!(e, input, Input);
+            restore!(e, team, Team);
+            restore!(e, health, Health);
+            restore!(e, sword_collider, SwordCollider);
+            restore!(e, body_weapon_collider, BodyWeaponCollider);
+            restore!(e, body_defense_collider, BodyDefenseCollider);
+            restore!(e, move_target, MoveTarget);
+            restore!(e, position, Position);
+            restore!(e, direction, Direction);
+            restore!(e, velocity, Velocity);
+            restore!(e, character_animator, CharacterAnimator);
+            restore!(e, health_bar, StatusBarView<ForHealth>);
+            restore!(e, character_view, CharacterView);
+            restore!(e, weapon, Weapon);
+            restore!(e, armor, Armor);
+            restore!(e, hit_registry, HitRegistry);
+            restore!(e, invulnerable, Invulnerable);
+            restore!(e, mass, Mass);
+            restore!(e, knockback, Knockback);
+            restore!(e, projectile, Projectile);
+            restore!(e, projectile_collider, ProjectileCollider);
+            restore!(e, projectile_view, ProjectileView);
+            restore!(e, launcher, Launcher);
+            restore!(e, keep_distance, KeepDistance);
+            restore!(e, shield, Shield);
+            restore!(e, shield_collider, ShieldCollider);
+            restore!(e, stamina, Stamina);
+            restore!(e, stamina_bar, StatusBarView<ForStamina>);
+            restore!(e, status_effects, StatusEffects);
+            restore!(e, hazard, Hazard);
+            restore!(e, combo, Combo);
+            restore!(e, pickup, Pickup);
+            restore!(e, pickup_collider, PickupCollider);
+            restore!(e, pickup_view, PickupView);
+            restore!(e, collector, Collector);
+            restore!(e, damage_boost, DamageBoost);
+            restore!(e, loot, Loot);
+            restore!(e, enemy_ai, EnemyAi);
+            restore!(e, targeting, Targeting);
+            restore!(e, target_priority, TargetPriority);
+            restore!(e, static_collider, StaticCollider);
+            restore!(e, path_follower, PathFollower);
+            restore!(e, steering, Steering);
+            restore!(e, behavior, Behavior);
+            restore!(e, ai_input, AiInput);
+        }
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_world_does_not_reuse_ids() {
+        let mut world = World::default();
+        let ids: Vec<EntityID> = (0..3)
+            .map(|i| add_entity!(world; Position(Vector::new(i as f32, 0f32))))
+            .collect();
+        let mut loaded = World::load(&world.save()).unwrap();
+        let id = add_entity!(loaded; Position(Vector::ZERO));
+        assert!(!ids.contains(&id));
+        assert_eq!(component!(loaded, Position).iter().count(), 4);
+    }
+}
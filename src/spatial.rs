@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+/// Cell size that fits the characters and weapon reach of the current prefabs.
+pub(crate) const DEFAULT_CELL_SIZE: f32 = 64f32;
+
+#[derive(Clone)]
+struct Entry<T> {
+    item: T,
+    x: f32,
+    y: f32,
+    radius: f32,
+}
+
+/// Uniform grid broadphase. Items are bounding circles; each one is registered in every cell
+/// its bounding box touches, so a query only has to look at the cells around it.
+///
+/// The grid is meant to be cleared and refilled every tick.
+#[derive(Clone)]
+pub(crate) struct SpatialGrid<T> {
+    cell_size: f32,
+    entries: Vec<Entry<T>>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl<T> Default for SpatialGrid<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl<T> SpatialGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            entries: Vec::new(),
+            cells: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Copy> SpatialGrid<T> {
+    /// Removes every item but keeps the allocated cells for the next fill.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.cells.values_mut().for_each(|cell| cell.clear());
+    }
+
+    pub fn insert(&mut self, item: T, x: f32, y: f32, radius: f32) {
+        let index = self.entries.len();
+        self.entries.push(Entry { item, x, y, radius });
+        let (min, max) = self.cell_range(x, y, radius);
+        for cx in min.0..=max.0 {
+            for cy in min.1..=max.1 {
+                self.cells.entry((cx, cy)).or_default().push(index);
+            }
+        }
+    }
+
+    /// Items whose bounding circle overlaps the circle at `(x, y)`, each reported once.
+    pub fn query(&self, x: f32, y: f32, radius: f32) -> Vec<T> {
+        let mut found = Vec::new();
+        let (min, max) = self.cell_range(x, y, radius);
+        for cx in min.0..=max.0 {
+            for cy in min.1..=max.1 {
+                if let Some(cell) = self.cells.get(&(cx, cy)) {
+                    found.extend(cell.iter().cloned().filter(|i| {
+                        let e = &self.entries[*i];
+                        let reach = e.radius + radius;
+                        (e.x - x) * (e.x - x) + (e.y - y) * (e.y - y) <= reach * reach
+                    }));
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found.into_iter().map(|i| self.entries[i].item).collect()
+    }
+
+    fn cell_range(&self, x: f32, y: f32, radius: f32) -> ((i32, i32), (i32, i32)) {
+        let cell = |v: f32| (v / self.cell_size).floor() as i32;
+        (
+            (cell(x - radius), cell(y - radius)),
+            (cell(x + radius), cell(y + radius)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_items_across_cell_boundaries() {
+        let mut grid = SpatialGrid::new(10f32);
+        grid.insert(1, 9f32, 5f32, 1f32);
+        grid.insert(2, 11f32, 5f32, 1f32);
+        grid.insert(3, 20f32, 20f32, 2f32);
+        assert_eq!(grid.query(10f32, 5f32, 0.5f32), vec![1, 2]);
+        assert_eq!(grid.query(5f32, 5f32, 3.5f32), vec![1]);
+        // registered in four cells, reported once
+        assert_eq!(grid.query(20f32, 20f32, 1f32), vec![3]);
+        assert_eq!(grid.query(17f32, 17f32, 1f32), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn handles_negative_coordinates() {
+        let mut grid = SpatialGrid::new(10f32);
+        grid.insert(1, -1f32, -1f32, 0.5f32);
+        grid.insert(2, 1f32, 1f32, 0.5f32);
+        grid.insert(3, -15f32, -25f32, 1f32);
+        assert_eq!(grid.query(-1.2f32, -1.2f32, 0.5f32), vec![1]);
+        assert_eq!(grid.query(0f32, 0f32, 1f32), vec![1, 2]);
+        assert_eq!(grid.query(-14f32, -24f32, 0.5f32), vec![3]);
+        assert_eq!(grid.query(15f32, 25f32, 1f32), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn handles_radii_larger_than_a_cell() {
+        let mut grid = SpatialGrid::new(10f32);
+        grid.insert(1, 45f32, 5f32, 1f32);
+        grid.insert(2, -30f32, 0f32, 35f32);
+        assert_eq!(grid.query(5f32, 5f32, 40f32), vec![1, 2]);
+        assert_eq!(grid.query(5f32, 5f32, 38f32), vec![2]);
+        assert_eq!(grid.query(-60f32, 0f32, 0f32), vec![2]);
+        assert_eq!(grid.query(-70f32, 0f32, 0f32), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn clear_empties_the_grid() {
+        let mut grid = SpatialGrid::new(10f32);
+        grid.insert(1, 0f32, 0f32, 1f32);
+        grid.clear();
+        assert_eq!(grid.query(0f32, 0f32, 5f32), Vec::<i32>::new());
+        grid.insert(2, 0f32, 0f32, 1f32);
+        assert_eq!(grid.query(0f32, 0f32, 5f32), vec![2]);
+    }
+}
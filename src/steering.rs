@@ -0,0 +1,244 @@
+use crate::components::StaticCollider;
+use crate::snapshot::VectorDef;
+use quicksilver::prelude::*;
+use serde::{Deserialize, Serialize};
+
+fn default_arrive_radius() -> f32 {
+    40f32
+}
+
+fn default_neighbour_radius() -> f32 {
+    36f32
+}
+
+fn default_look_ahead() -> f32 {
+    24f32
+}
+
+/// How much each behaviour counts in the blend.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct SteeringWeights {
+    /// Full speed at the target, overshooting it.
+    pub seek: f32,
+    /// Like seek, but slowing down to stop on the target.
+    pub arrive: f32,
+    /// Away from allies that come too close.
+    pub separation: f32,
+    /// Toward the middle of nearby allies.
+    pub cohesion: f32,
+    /// Away from walls just ahead.
+    pub avoidance: f32,
+}
+
+impl Default for SteeringWeights {
+    fn default() -> Self {
+        Self {
+            seek: 0f32,
+            arrive: 1f32,
+            separation: 1.5f32,
+            cohesion: 0f32,
+            avoidance: 1f32,
+        }
+    }
+}
+
+/// Moves a character toward its `MoveTarget` by blending steering behaviours, instead of
+/// jumping to a velocity proportional to the distance.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Steering {
+    /// Pixels per second.
+    pub max_speed: f32,
+    /// Pixels per second squared.
+    pub max_acceleration: f32,
+    #[serde(default)]
+    pub weights: SteeringWeights,
+    /// Arrive slows down within this distance of the target.
+    #[serde(default = "default_arrive_radius")]
+    pub arrive_radius: f32,
+    /// Allies within this distance count for separation and cohesion.
+    #[serde(default = "default_neighbour_radius")]
+    pub neighbour_radius: f32,
+    /// Walls closer than this are steered away from.
+    #[serde(default = "default_look_ahead")]
+    pub look_ahead: f32,
+    /// Velocity steered to last tick, before knockback and animations.
+    #[serde(default, with = "VectorDef")]
+    pub velocity: Vector,
+}
+
+/// What one character's steering looks at this tick.
+pub(crate) struct SteeringInput<'a> {
+    pub position: Vector,
+    pub target: Vector,
+    /// `target` is a waypoint to pass through rather than the place to stop at.
+    pub passing: bool,
+    /// Positions of the allies within `neighbour_radius`.
+    pub neighbours: &'a [Vector],
+    pub walls: &'a [StaticCollider],
+    /// Scales `max_speed`, for slows and stuns.
+    pub speed_multiplier: f32,
+}
+
+fn clamp_len(v: Vector, max: f32) -> Vector {
+    if v.len() > max {
+        v.normalize() * max
+    } else {
+        v
+    }
+}
+
+impl Steering {
+    pub fn seek(&self, position: Vector, target: Vector) -> Vector {
+        if position == target {
+            return Vector::ZERO;
+        }
+        (target - position).normalize() * self.max_speed
+    }
+
+    pub fn arrive(&self, position: Vector, target: Vector) -> Vector {
+        let distance = target.distance(position);
+        if distance < 1f32 {
+            return Vector::ZERO;
+        }
+        let slowdown = (distance / self.arrive_radius.max(1f32)).min(1f32);
+        (target - position).normalize() * self.max_speed * slowdown
+    }
+
+    /// Pushes away from each neighbour, harder the closer it is.
+    pub fn separation(&self, position: Vector, neighbours: &[Vector]) -> Vector {
+        neighbours
+            .iter()
+            .filter(|other| **other != position)
+            .fold(Vector::ZERO, |sum, other| {
+                let distance = other.distance(position);
+                let strength = 1f32 - (distance / self.neighbour_radius).min(1f32);
+                sum + (position - *other).normalize() * self.max_speed * strength
+            })
+    }
+
+    pub fn cohesion(&self, position: Vector, neighbours: &[Vector]) -> Vector {
+        if neighbours.is_empty() {
+            return Vector::ZERO;
+        }
+        let center = neighbours
+            .iter()
+            .fold(Vector::ZERO, |sum, other| sum + *other)
+            / neighbours.len() as f32;
+        self.arrive(position, center)
+    }
+
+    /// Pushes away from walls within `look_ahead`, harder the closer they are.
+    pub fn avoidance(&self, position: Vector, walls: &[StaticCollider]) -> Vector {
+        walls.iter().fold(Vector::ZERO, |sum, wall| {
+            let closest = wall.closest_point(position);
+            let distance = closest.distance(position);
+            if closest == position || distance >= self.look_ahead {
+                return sum;
+            }
+            let strength = 1f32 - distance / self.look_ahead;
+            sum + (position - closest).normalize() * self.max_speed * strength
+        })
+    }
+
+    /// Blends the behaviours by weight and turns the last velocity toward the result, changing
+    /// it by no more than `max_acceleration`. Returns the new velocity.
+    pub fn update(&mut self, delta: f32, input: &SteeringInput) -> Vector {
+        let weights = &self.weights;
+        let toward = if input.passing {
+            self.seek(input.position, input.target) * (weights.seek + weights.arrive)
+        } else {
+            self.seek(input.position, input.target) * weights.seek
+                + self.arrive(input.position, input.target) * weights.arrive
+        };
+        let desired = toward
+            + self.separation(input.position, input.neighbours) * weights.separation
+            + self.cohesion(input.position, input.neighbours) * weights.cohesion
+            + self.avoidance(input.position, input.walls) * weights.avoidance;
+        let max_speed = self.max_speed * input.speed_multiplier;
+        let change = clamp_len(
+            clamp_len(desired, max_speed) - self.velocity,
+            self.max_acceleration * delta,
+        );
+        self.velocity = clamp_len(self.velocity + change, max_speed);
+        self.velocity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steering() -> Steering {
+        Steering {
+            max_speed: 100f32,
+            max_acceleration: 200f32,
+            weights: SteeringWeights::default(),
+            arrive_radius: default_arrive_radius(),
+            neighbour_radius: default_neighbour_radius(),
+            look_ahead: default_look_ahead(),
+            velocity: Vector::ZERO,
+        }
+    }
+
+    fn toward(target: Vector, neighbours: &[Vector], speed_multiplier: f32) -> SteeringInput {
+        SteeringInput {
+            position: Vector::ZERO,
+            target,
+            passing: false,
+            neighbours,
+            walls: &[],
+            speed_multiplier,
+        }
+    }
+
+    fn assert_close(a: Vector, b: Vector) {
+        assert!(a.distance(b) < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn acceleration_is_clamped() {
+        let mut steering = steering();
+        let far = Vector::new(1000f32, 0f32);
+        assert_close(
+            steering.update(0.1f32, &toward(far, &[], 1f32)),
+            Vector::new(20f32, 0f32),
+        );
+        assert_close(
+            steering.update(0.1f32, &toward(far, &[], 1f32)),
+            Vector::new(40f32, 0f32),
+        );
+        // braking is limited the same way
+        let here = Vector::ZERO;
+        assert_close(
+            steering.update(0.1f32, &toward(here, &[], 1f32)),
+            Vector::new(20f32, 0f32),
+        );
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        let mut steering = steering();
+        let far = Vector::new(1000f32, 0f32);
+        // an ally right behind pushes the same way as arrive
+        let behind = [Vector::new(-5f32, 0f32)];
+        let velocity = steering.update(10f32, &toward(far, &behind, 1f32));
+        assert_close(velocity, Vector::new(100f32, 0f32));
+    }
+
+    #[test]
+    fn speed_multiplier_scales_max_speed() {
+        let mut steering = steering();
+        let far = Vector::new(0f32, 1000f32);
+        assert_close(
+            steering.update(10f32, &toward(far, &[], 0.5f32)),
+            Vector::new(0f32, 50f32),
+        );
+        // a stun stops the character at once, whatever the acceleration
+        steering.max_acceleration = 1f32;
+        assert_close(
+            steering.update(0.1f32, &toward(far, &[], 0f32)),
+            Vector::ZERO,
+        );
+    }
+}
@@ -1,458 +1,2293 @@
-// use crate::components::*;
-// use crate::*;
-// use std::marker::PhantomData;
-// use std::ops::Add;
-
-// pub(crate) trait SystemInterface {
-//     type Update;
-//     type Refer;
-// }
-// pub(crate) trait SystemProcess: SystemInterface {
-//     fn process(update: &mut Self::Update, _ref: &Self::Refer);
-// }
-
-// pub(crate) struct System<U, R> {
-//     phantom: PhantomData<(U, R)>,
-// }
-// impl<U, R> SystemInterface for System<U, R> {
-//     type Update = U;
-//     type Refer = R;
-// }
-
-// pub(crate) struct ForObserverSet();
-// pub(crate) struct ForObserverCheck();
-
-// impl<V, C> SystemProcess
-//     for System<CContainer<ValueObserver<V, C>>, (&CContainer<C>, ForObserverSet)>
-// where
-//     V: PartialEq + Copy,
-// {
-//     fn process(observers: &mut Self::Update, (components, _): &Self::Refer) {
-//         observers
-//             .iter_mut()
-//             .zip_entity(components)
-//             .for_each(|(_, observer, component)| {
-//                 observer.set(component);
-//             });
-//     }
-// }
-
-// impl<V, C> SystemProcess
-//     for System<CContainer<ValueObserver<V, C>>, (&CContainer<C>, ForObserverCheck)>
-// where
-//     V: PartialEq + Copy,
-// {
-//     fn process(observers: &mut Self::Update, components: &Self::Refer) {
-//         observers
-//             .iter_mut()
-//             .zip_entity(components.0)
-//             .for_each(|(_, observer, component)| {
-//                 observer.check(component);
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<Velocity>, CContainer<Input>> {
-//     fn process(velocities: &mut Self::Update, inputs: &Self::Refer) {
-//         velocities
-//             .iter_mut()
-//             .zip_entity(inputs)
-//             .for_each(|(_, velocity, input)| {
-//                 velocity.0.x = 0f32;
-//                 velocity.0.y = 0f32;
-//                 if input.left {
-//                     velocity.0.x = -2f32;
-//                 }
-//                 if input.right {
-//                     velocity.0.x = 2f32;
-//                 }
-//                 if input.up {
-//                     velocity.0.y = -2f32;
-//                 }
-//                 if input.down {
-//                     velocity.0.y = 2f32;
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<MoveTarget>, (&CContainer<Team>, &CContainer<Position>)> {
-//     fn process(move_targets: &mut Self::Update, (teams, positions): &Self::Refer) {
-//         move_targets
-//             .iter_mut()
-//             .zip_entity2(teams, positions)
-//             .for_each(|(_, target, self_team, self_pos)| {
-//                 teams
-//                     .iter()
-//                     .filter(|(_, team)| team.team_id() != self_team.team_id())
-//                     .for_each(|(entity_id, _)| {
-//                         if let Some(pos) = CContainer::<Position>::get(positions, entity_id) {
-//                             let distance = pos.0.distance((self_pos.0.x, self_pos.0.y));
-//                             if distance < 100f32 {
-//                                 target.0.x = pos.0.x;
-//                                 target.0.y = pos.0.y;
-//                             } else {
-//                                 target.0.x = self_pos.0.x;
-//                                 target.0.y = self_pos.0.y;
-//                             }
-//                         }
-//                     });
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<CContainer<Velocity>, (&CContainer<Position>, &CContainer<MoveTarget>)>
-// {
-//     fn process(velocities: &mut Self::Update, pos_tgt: &Self::Refer) {
-//         velocities
-//             .iter_mut()
-//             .zip_entity2(pos_tgt.0, pos_tgt.1)
-//             .for_each(|(_, vel, pos, target)| {
-//                 let mut tmp = Vector::default();
-//                 tmp.x = target.0.x - pos.0.x;
-//                 tmp.y = target.0.y - pos.0.y;
-//                 vel.0.x = tmp.x / 50f32;
-//                 vel.0.y = tmp.y / 50f32;
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<CContainer<Direction>, (&CContainer<Position>, &CContainer<MoveTarget>)>
-// {
-//     fn process(directions: &mut Self::Update, (positions, targets): &Self::Refer) {
-//         directions
-//             .iter_mut()
-//             .zip_entity2(positions, targets)
-//             .for_each(|(_, dir, position, target)| {
-//                 if position.0 != target.0 {
-//                     *dir = (target.0.y - position.0.y).atan2(target.0.x - position.0.x);
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<CContainer<Velocity>, (&CContainer<CharacterView>, &CContainer<CharacterAnimator>)>
-// {
-//     fn process(velocities: &mut Self::Update, (views, animators): &Self::Refer) {
-//         velocities
-//             .iter_mut()
-//             .zip_entity2(views, animators)
-//             .for_each(|(_, velocity, view, animator)| {
-//                 if let Some(val) = animator.value() {
-//                     if val.move_forward != 0f32 {
-//                         velocity.0.x = view.direction.cos() * val.move_forward;
-//                         velocity.0.y = view.direction.sin() * val.move_forward;
-//                     }
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<Position>, CContainer<Velocity>> {
-//     fn process(positions: &mut Self::Update, velocities: &Self::Refer) {
-//         positions
-//             .iter_mut()
-//             .zip_entity(velocities)
-//             .for_each(|(_, pos, vel)| {
-//                 pos.0.x += vel.0.x;
-//                 pos.0.y += vel.0.y;
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<Direction>, CContainer<Input>> {
-//     fn process(directions: &mut Self::Update, inputs: &Self::Refer) {
-//         directions
-//             .iter_mut()
-//             .zip_entity(inputs)
-//             .for_each(|(_, direction, input)| {
-//                 if input.left {
-//                     *direction = PI;
-//                     if input.up {
-//                         *direction = FRAC_PI_4 * 5f32;
-//                     }
-//                     if input.down {
-//                         *direction = FRAC_PI_4 * 3f32;
-//                     }
-//                 } else if input.right {
-//                     *direction = 0f32;
-//                     if input.up {
-//                         *direction = FRAC_PI_4 * 7f32;
-//                     }
-//                     if input.down {
-//                         *direction = FRAC_PI_4;
-//                     }
-//                 } else {
-//                     if input.up {
-//                         *direction = FRAC_PI_2 * 3f32;
-//                     }
-//                     if input.down {
-//                         *direction = FRAC_PI_2;
-//                     }
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<
-//         CContainer<SwordCollider>,
-//         (&CContainer<CharacterView>, &CContainer<CharacterAnimator>),
-//     >
-// {
-//     fn process(sword_colliders: &mut Self::Update, (views, animators): &Self::Refer) {
-//         sword_colliders
-//             .iter_mut()
-//             .zip_entity2(views, animators)
-//             .for_each(|(_, collider, view, animator)| {
-//                 let dir = view.direction + view.weapon_direction;
-//                 collider.line.a = view.position;
-//                 collider.line.b.x = view.position.x + dir.cos() * view.radius * 1.8f32;
-//                 collider.line.b.y = view.position.y + dir.sin() * view.radius * 1.8f32;
-
-//                 collider.active = false;
-//                 if let Some(id) = animator.playing_id() {
-//                     if id == CharacterAnimID::Attack {
-//                         collider.active = true;
-//                     }
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<BodyWeaponCollider>, CContainer<CharacterView>> {
-//     fn process(body_weapon_colliders: &mut Self::Update, views: &Self::Refer) {
-//         body_weapon_colliders
-//             .iter_mut()
-//             .zip_entity(views)
-//             .for_each(|(_, collider, view)| {
-//                 collider.circle.pos = view.position;
-//                 collider.circle.radius = view.radius;
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<
-//         CContainer<BodyDefenseCollider>,
-//         (
-//             &CContainer<CharacterView>,
-//             &CContainer<SwordCollider>,
-//             &CContainer<BodyWeaponCollider>,
-//             &CContainer<Team>,
-//         ),
-//     >
-// {
-//     fn process(
-//         body_defenses: &mut Self::Update,
-//         (character_views, sword_colliders, body_weapon_colliders, teams): &Self::Refer,
-//     ) {
-//         body_defenses
-//             .iter_mut()
-//             .zip_entity2(character_views, teams)
-//             .for_each(|(defense_entity_id, body_defense, view, defense_team)| {
-//                 body_defense.hit = false;
-//                 body_defense.circle.pos = view.position;
-//                 body_defense.circle.radius = view.radius;
-
-//                 sword_colliders.iter().zip_entity(teams).for_each(
-//                     |(sword_entity_id, sword_collider, sword_team)| {
-//                         if defense_entity_id == sword_entity_id {
-//                             return;
-//                         }
-//                         if defense_team.team_id() == sword_team.team_id() {
-//                             return;
-//                         }
-//                         if sword_collider.is_collided(body_defense) {
-//                             body_defense.hit = true;
-//                         }
-//                     },
-//                 );
-
-//                 body_weapon_colliders.iter().zip_entity(teams).for_each(
-//                     |(weapon_entity_id, weapon_collider, weapon_team)| {
-//                         if defense_entity_id == weapon_entity_id {
-//                             return;
-//                         }
-//                         if defense_team.team_id() == weapon_team.team_id() {
-//                             return;
-//                         }
-//                         if weapon_collider.is_collided(body_defense) {
-//                             body_defense.hit = true;
-//                         }
-//                     },
-//                 );
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<Health>, CContainer<BodyDefenseCollider>> {
-//     fn process(healths: &mut Self::Update, bodies: &Self::Refer) {
-//         healths
-//             .iter_mut()
-//             .zip_entity(bodies)
-//             .for_each(|(_, health, body)| {
-//                 if body.hit {
-//                     health.current_health -= 10;
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<CharacterAnimator>, ()> {
-//     fn process(animators: &mut Self::Update, _: &Self::Refer) {
-//         animators
-//             .iter_mut()
-//             .for_each(|(_, animator)| animator.update());
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<CharacterAnimator>, CContainer<Input>> {
-//     fn process(animators: &mut Self::Update, inputs: &Self::Refer) {
-//         animators
-//             .iter_mut()
-//             .zip_entity(inputs)
-//             .for_each(|(_, animator, input)| {
-//                 if let Some(id) = animator.playing_id() {
-//                     if id == CharacterAnimID::Attack && animator.is_end() {
-//                         animator.play(CharacterAnimID::Wait);
-//                     }
-//                     if input.attack && id != CharacterAnimID::Attack {
-//                         animator.play(CharacterAnimID::Attack);
-//                     }
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<CharacterAnimator>, CContainer<BodyDefenseCollider>> {
-//     fn process(animators: &mut Self::Update, defense_colliders: &Self::Refer) {
-//         animators
-//             .iter_mut()
-//             .zip_entity(defense_colliders)
-//             .for_each(|(_, animator, collider)| {
-//                 if let Some(id) = animator.playing_id() {
-//                     if id == CharacterAnimID::Damaged && animator.is_end() {
-//                         animator.play(CharacterAnimID::Wait);
-//                     }
-//                     if collider.hit && id != CharacterAnimID::Damaged {
-//                         animator.play(CharacterAnimID::Damaged);
-//                     }
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess for System<CContainer<CharacterView>, CContainer<CharacterAnimator>> {
-//     fn process(views: &mut Self::Update, animators: &Self::Refer) {
-//         views
-//             .iter_mut()
-//             .zip_entity(animators)
-//             .for_each(|(_, view, animator)| {
-//                 if let Some(val) = animator.value() {
-//                     view.radius_scale = val.radius_scale;
-//                     view.weapon_direction = val.weapon_direction;
-//                 }
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<CContainer<CharacterView>, (&CContainer<Position>, &CContainer<Direction>)>
-// {
-//     fn process(views: &mut Self::Update, (positions, directions): &Self::Refer) {
-//         views
-//             .iter_mut()
-//             .zip_entity2(positions, directions)
-//             .for_each(|(_, view, pos, dir)| {
-//                 view.position.x = pos.0.x;
-//                 view.position.y = pos.0.y;
-//                 view.direction = *dir;
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<CContainer<StatusBarView<StatusBarType::Health>>, CContainer<Health>>
-// {
-//     fn process(status_bars: &mut Self::Update, healths: &Self::Refer) {
-//         status_bars
-//             .iter_mut()
-//             .zip_entity(healths)
-//             .for_each(|(_, bar, health)| {
-//                 bar.current_length = (bar.frame_length as f32 * health.ratio()) as i32;
-//             });
-//     }
-// }
-
-// impl SystemProcess
-//     for System<CContainer<StatusBarView<StatusBarType::Health>>, CContainer<CharacterView>>
-// {
-//     fn process(status_bars: &mut Self::Update, character_views: &Self::Refer) {
-//         status_bars
-//             .iter_mut()
-//             .zip_entity(character_views)
-//             .for_each(|(_, bar, chara)| {
-//                 bar.position = chara.position + Vector::new(10f32, -10f32);
-//                 if bar.animated_length != bar.current_length {
-//                     let diff = bar.current_length - bar.animated_length;
-//                     let mov = diff / diff.abs();
-//                     bar.animated_length += mov;
-//                 }
-//             })
-//     }
-// }
-
-// impl SystemProcess for System<Window, CContainer<StatusBarView<StatusBarType::Health>>> {
-//     fn process(window: &mut Self::Update, views: &Self::Refer) {
-//         views.iter().for_each(|(_, view)| {
-//             window.draw(
-//                 &Rectangle::new(
-//                     (view.position.x - 1f32, view.position.y - 1f32),
-//                     (view.frame_length + 1i32, 7i32),
-//                 ),
-//                 Col(Color::BLACK),
-//             );
-//             window.draw(
-//                 &Rectangle::new(
-//                     (view.position.x, view.position.y),
-//                     (view.animated_length, 6f32),
-//                 ),
-//                 Col(Color::RED),
-//             );
-//             window.draw(
-//                 &Rectangle::new(
-//                     (view.position.x, view.position.y),
-//                     (view.current_length, 6f32),
-//                 ),
-//                 Col(view.color),
-//             );
-//         });
-//     }
-// }
-
-// impl SystemProcess for System<Window, CContainer<CharacterView>> {
-//     fn process(window: &mut Self::Update, views: &Self::Refer) {
-//         views.iter().for_each(|(_, view)| {
-//             window.draw(
-//                 &Circle::new(
-//                     (view.position.x, view.position.y),
-//                     view.radius * view.radius_scale,
-//                 ),
-//                 Col(view.color),
-//             );
-//             let dir = view.direction + view.weapon_direction;
-//             let line_end = (
-//                 view.position.x + dir.cos() * view.radius * 1.8f32,
-//                 view.position.y + dir.sin() * view.radius * 1.8f32,
-//             );
-//             window.draw(
-//                 &Line::new((view.position.x, view.position.y), line_end),
-//                 Col(view.color),
-//             );
-//         });
-//     }
-// }
+use static_ecs::*;
+
+use crate::components::*;
+use crate::events::*;
+use crate::navigation::*;
+use crate::simulation::*;
+use crate::*;
+use quicksilver::prelude::*;
+use static_ecs::component::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::f32::consts::*;
+use std::fmt;
+
+/// Hero walking speed in pixels per second.
+const HERO_SPEED: f32 = 120f32;
+/// Fraction of the distance to `MoveTarget` an enemy closes per second.
+const CHASE_RATE: f32 = 1.2f32;
+/// Pixels per second the damage trail of a status bar shrinks.
+const STATUS_BAR_SPEED: f32 = 60f32;
+/// Vertical distance between stacked status bars.
+const STATUS_BAR_SPACING: f32 = 8f32;
+
+pub(crate) type SystemFn = fn(&mut World, &mut Resources);
+
+/// Coarse phases of a tick. Every system of a stage runs before any system of a later stage.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub(crate) enum Stage {
+    Input,
+    Ai,
+    Combat,
+    Movement,
+    Animation,
+    View,
+}
+
+/// A named system together with the components it touches and where it runs.
+#[derive(Clone)]
+pub(crate) struct SystemDesc {
+    name: &'static str,
+    stage: Stage,
+    reads: Vec<&'static str>,
+    writes: Vec<&'static str>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    enabled: bool,
+    run: SystemFn,
+}
+
+impl SystemDesc {
+    pub fn new(name: &'static str, stage: Stage, run: SystemFn) -> Self {
+        Self {
+            name,
+            stage,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            enabled: true,
+            run,
+        }
+    }
+    pub fn reads(mut self, components: &[&'static str]) -> Self {
+        self.reads.extend_from_slice(components);
+        self
+    }
+    pub fn writes(mut self, components: &[&'static str]) -> Self {
+        self.writes.extend_from_slice(components);
+        self
+    }
+    pub fn before(mut self, system: &'static str) -> Self {
+        self.before.push(system);
+        self
+    }
+    pub fn after(mut self, system: &'static str) -> Self {
+        self.after.push(system);
+        self
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+    fn uses(&self, component: &str) -> bool {
+        self.reads.iter().chain(self.writes.iter()).any(|c| *c == component)
+    }
+}
+
+impl fmt::Display for SystemDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?}/{} reads {:?} writes {:?}",
+            self.stage, self.name, self.reads, self.writes
+        )
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ScheduleError {
+    Duplicate(&'static str),
+    UnknownSystem(&'static str, &'static str),
+    Cycle(Vec<&'static str>),
+    /// A system writes a component that another system of its stage reads or writes, and the
+    /// two are not ordered. Holds the writer, the other system and the component.
+    Conflict(&'static str, &'static str, &'static str),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleError::Duplicate(name) => write!(f, "system {} is added twice", name),
+            ScheduleError::UnknownSystem(name, other) => {
+                write!(f, "system {} is ordered against unknown system {}", name, other)
+            }
+            ScheduleError::Cycle(names) => {
+                write!(f, "ordering constraints form a cycle between {:?}", names)
+            }
+            ScheduleError::Conflict(writer, other, component) => write!(
+                f,
+                "system {} writes {} which {} also uses, but the two are not ordered",
+                writer, component, other
+            ),
+        }
+    }
+}
+
+/// Runs systems in stage order, honouring `before`/`after` constraints. Systems without a
+/// constraint between them run in the order they were added, but a system that writes a
+/// component must be ordered against every system of its stage that reads or writes it.
+/// Queued commands are applied after each stage.
+#[derive(Default, Clone)]
+pub(crate) struct Scheduler {
+    systems: Vec<SystemDesc>,
+    order: Option<Vec<usize>>,
+}
+
+impl Scheduler {
+    pub fn add(&mut self, system: SystemDesc) -> Result<(), ScheduleError> {
+        if self.systems.iter().any(|s| s.name == system.name) {
+            return Err(ScheduleError::Duplicate(system.name));
+        }
+        self.systems.push(system);
+        self.order = None;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<SystemDesc> {
+        let index = self.systems.iter().position(|s| s.name == name)?;
+        self.order = None;
+        Some(self.systems.remove(index))
+    }
+
+    /// Returns false when there is no system called `name`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.systems.iter_mut().find(|s| s.name == name) {
+            Some(system) => {
+                system.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.systems.iter().any(|s| s.name == name && s.enabled)
+    }
+
+    /// Systems in the order they will run.
+    pub fn ordered(&mut self) -> Result<Vec<&SystemDesc>, ScheduleError> {
+        self.resolve()?;
+        let systems = &self.systems;
+        Ok(self
+            .order
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|i| &systems[*i])
+            .collect())
+    }
+
+    pub fn run(&mut self, world: &mut World, resources: &mut Resources) -> Result<(), ScheduleError> {
+        self.resolve()?;
+        let mut stage = None;
+        for index in self.order.as_ref().unwrap().iter() {
+            let system = &self.systems[*index];
+            if stage.is_some() && stage != Some(system.stage) {
+                resources.apply_commands(world);
+            }
+            stage = Some(system.stage);
+            if system.enabled {
+                (system.run)(world, resources);
+            }
+        }
+        resources.apply_commands(world);
+        Ok(())
+    }
+
+    fn resolve(&mut self) -> Result<(), ScheduleError> {
+        if self.order.is_some() {
+            return Ok(());
+        }
+
+        let index_of: HashMap<&str, usize> = self
+            .systems
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.name, i))
+            .collect();
+
+        // edges[a] holds every system that has to run after a
+        let mut edges = vec![BTreeSet::<usize>::new(); self.systems.len()];
+        for (i, system) in self.systems.iter().enumerate() {
+            for other in system.before.iter() {
+                let j = *index_of
+                    .get(other)
+                    .ok_or(ScheduleError::UnknownSystem(system.name, other))?;
+                edges[i].insert(j);
+            }
+            for other in system.after.iter() {
+                let j = *index_of
+                    .get(other)
+                    .ok_or(ScheduleError::UnknownSystem(system.name, other))?;
+                edges[j].insert(i);
+            }
+            for (j, later) in self.systems.iter().enumerate() {
+                if system.stage < later.stage {
+                    edges[i].insert(j);
+                }
+            }
+        }
+
+        let mut incoming = vec![0; self.systems.len()];
+        edges.iter().flatten().for_each(|j| incoming[*j] += 1);
+
+        // Kahn's algorithm, always taking the earliest added ready system so the result is stable
+        let mut ready: BTreeSet<usize> = (0..self.systems.len())
+            .filter(|i| incoming[*i] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.systems.len());
+        while let Some(i) = ready.iter().next().cloned() {
+            ready.remove(&i);
+            order.push(i);
+            for j in edges[i].iter() {
+                incoming[*j] -= 1;
+                if incoming[*j] == 0 {
+                    ready.insert(*j);
+                }
+            }
+        }
+
+        if order.len() != self.systems.len() {
+            let names = (0..self.systems.len())
+                .filter(|i| incoming[*i] > 0)
+                .map(|i| self.systems[i].name)
+                .collect();
+            return Err(ScheduleError::Cycle(names));
+        }
+
+        let reachable: Vec<BTreeSet<usize>> = (0..self.systems.len())
+            .map(|i| {
+                let mut seen = BTreeSet::new();
+                let mut stack = vec![i];
+                while let Some(k) = stack.pop() {
+                    for j in edges[k].iter() {
+                        if seen.insert(*j) {
+                            stack.push(*j);
+                        }
+                    }
+                }
+                seen
+            })
+            .collect();
+        for (i, a) in self.systems.iter().enumerate() {
+            for (j, b) in self.systems.iter().enumerate().skip(i + 1) {
+                if a.stage != b.stage || reachable[i].contains(&j) || reachable[j].contains(&i) {
+                    continue;
+                }
+                if let Some(component) = a.writes.iter().find(|c| b.uses(c)) {
+                    return Err(ScheduleError::Conflict(a.name, b.name, *component));
+                }
+                if let Some(component) = b.writes.iter().find(|c| a.uses(c)) {
+                    return Err(ScheduleError::Conflict(b.name, a.name, *component));
+                }
+            }
+        }
+        self.order = Some(order);
+        Ok(())
+    }
+}
+
+/// The gameplay systems of a tick.
+pub(crate) fn default_scheduler() -> Scheduler {
+    let systems = vec![
+        SystemDesc::new("apply_input", Stage::Input, apply_input)
+            .reads(&["StatusEffects"])
+            .writes(&["Input"]),
+        SystemDesc::new("index_characters", Stage::Ai, index_characters)
+            .reads(&["Position", "CharacterView"]),
+        SystemDesc::new("build_navigation", Stage::Ai, build_navigation)
+            .reads(&["StaticCollider"]),
+        SystemDesc::new("select_target", Stage::Ai, select_target)
+            .reads(&["Team", "Position", "Health", "TargetPriority"])
+            .writes(&["Targeting"])
+            .after("index_characters"),
+        SystemDesc::new("chase_target", Stage::Ai, chase_target)
+            .reads(&["Position", "Targeting", "StatusEffects"])
+            .writes(&["MoveTarget"])
+            .after("select_target"),
+        SystemDesc::new("keep_distance", Stage::Ai, keep_distance)
+            .reads(&[
+                "Position",
+                "Targeting",
+                "CharacterAnimator",
+                "Stamina",
+                "StatusEffects",
+            ])
+            .writes(&["KeepDistance", "MoveTarget", "Direction", "AiInput"])
+            .after("select_target")
+            .after("chase_target"),
+        SystemDesc::new("enemy_ai", Stage::Ai, enemy_ai)
+            .reads(&[
+                "Position",
+                "Health",
+                "Targeting",
+                "CharacterAnimator",
+                "StatusEffects",
+            ])
+            .writes(&["EnemyAi", "MoveTarget", "Direction", "AiInput"])
+            .after("select_target")
+            .after("chase_target")
+            .after("keep_distance"),
+        SystemDesc::new("behavior_trees", Stage::Ai, behavior_trees)
+            .reads(&[
+                "Position",
+                "Health",
+                "Targeting",
+                "CharacterAnimator",
+                "StatusEffects",
+            ])
+            .writes(&["Behavior", "MoveTarget", "Direction", "AiInput"])
+            .after("select_target")
+            .after("chase_target")
+            .after("enemy_ai"),
+        SystemDesc::new("follow_path", Stage::Ai, follow_path)
+            .reads(&["Position"])
+            .writes(&["PathFollower", "MoveTarget"])
+            .after("build_navigation")
+            .after("chase_target")
+            .after("keep_distance")
+            .after("enemy_ai")
+            .after("behavior_trees"),
+        SystemDesc::new("sword_collider", Stage::Combat, sword_collider)
+            .reads(&["CharacterView", "CharacterAnimator"])
+            .writes(&["SwordCollider"]),
+        SystemDesc::new("body_weapon_collider", Stage::Combat, body_weapon_collider)
+            .reads(&["CharacterView"])
+            .writes(&["BodyWeaponCollider"]),
+        SystemDesc::new("projectile_collider", Stage::Combat, projectile_collider)
+            .reads(&["Position"])
+            .writes(&["ProjectileCollider"]),
+        SystemDesc::new("index_attacks", Stage::Combat, index_attacks)
+            .reads(&["SwordCollider", "BodyWeaponCollider", "ProjectileCollider"])
+            .after("sword_collider")
+            .after("body_weapon_collider")
+            .after("projectile_collider"),
+        SystemDesc::new("body_defense_collider", Stage::Combat, body_defense_collider)
+            .reads(&[
+                "CharacterView",
+                "Team",
+                "SwordCollider",
+                "BodyWeaponCollider",
+                "ProjectileCollider",
+                "Projectile",
+            ])
+            .writes(&["BodyDefenseCollider"])
+            .after("index_attacks"),
+        SystemDesc::new("hit_registry", Stage::Combat, hit_registry)
+            .reads(&["CharacterAnimator"])
+            .writes(&["HitRegistry"]),
+        SystemDesc::new("invulnerability_timer", Stage::Combat, invulnerability_timer)
+            .writes(&["Invulnerable"]),
+        SystemDesc::new("filter_hits", Stage::Combat, filter_hits)
+            .reads(&["Invulnerable"])
+            .writes(&["HitRegistry"])
+            .after("body_defense_collider")
+            .after("hit_registry")
+            .after("invulnerability_timer"),
+        SystemDesc::new("despawn_projectiles", Stage::Combat, despawn_projectiles)
+            .reads(&["Projectile"])
+            .after("filter_hits"),
+        SystemDesc::new("stamina_regen", Stage::Combat, stamina_regen)
+            .writes(&["Stamina"]),
+        SystemDesc::new("raise_shield", Stage::Combat, raise_shield)
+            .reads(&["Input", "AiInput", "CharacterAnimator"])
+            .writes(&["Shield"]),
+        SystemDesc::new("shield_collider", Stage::Combat, shield_collider)
+            .reads(&["Shield", "CharacterView"])
+            .writes(&["ShieldCollider"])
+            .after("raise_shield"),
+        SystemDesc::new("block_hits", Stage::Combat, block_hits)
+            .reads(&["Shield", "ShieldCollider", "Position", "Stamina"])
+            .after("shield_collider")
+            .after("despawn_projectiles")
+            .after("stamina_regen"),
+        SystemDesc::new("block_stamina", Stage::Combat, block_stamina)
+            .writes(&["Stamina"])
+            .after("block_hits"),
+        SystemDesc::new("resolve_hits", Stage::Combat, resolve_hits)
+            .reads(&[
+                "Weapon",
+                "Armor",
+                "Projectile",
+                "Combo",
+                "DamageBoost",
+                "Position",
+            ])
+            .after("damage_boost_timer")
+            .after("block_hits"),
+        SystemDesc::new("collect_pickups", Stage::Combat, collect_pickups)
+            .reads(&["Pickup", "PickupCollider", "Collector", "BodyDefenseCollider"])
+            .after("body_defense_collider"),
+        SystemDesc::new("apply_pickups", Stage::Combat, apply_pickups)
+            .writes(&["Health", "Collector"])
+            .after("collect_pickups")
+            .before("damage"),
+        SystemDesc::new("damage_boost_timer", Stage::Combat, damage_boost_timer)
+            .writes(&["DamageBoost"])
+            .before("apply_pickups"),
+        SystemDesc::new("status_effects", Stage::Combat, status_effects)
+            .writes(&["StatusEffects"])
+            .before("damage"),
+        SystemDesc::new("hazard_effects", Stage::Combat, hazard_effects)
+            .reads(&["Position"])
+            .writes(&["Hazard", "StatusEffects"])
+            .after("status_effects"),
+        SystemDesc::new("inflict_effects", Stage::Combat, inflict_effects)
+            .writes(&["StatusEffects"])
+            .after("resolve_hits")
+            .after("status_effects")
+            .after("hazard_effects"),
+        SystemDesc::new("damage", Stage::Combat, damage)
+            .writes(&["Health"])
+            .after("resolve_hits"),
+        SystemDesc::new("apply_knockback", Stage::Combat, apply_knockback)
+            .reads(&["Mass"])
+            .writes(&["Knockback"])
+            .after("damage"),
+        SystemDesc::new("grant_invulnerability", Stage::Combat, grant_invulnerability)
+            .writes(&["Invulnerable"])
+            .after("damage"),
+        SystemDesc::new("provoke_neutrals", Stage::Combat, provoke_neutrals)
+            .reads(&["Team"])
+            .after("damage"),
+        SystemDesc::new("despawn_dead", Stage::Combat, despawn_dead).after("damage"),
+        SystemDesc::new("drop_loot", Stage::Combat, drop_loot)
+            .reads(&["Loot", "Position"])
+            .after("damage"),
+        SystemDesc::new("velocity_from_input", Stage::Movement, velocity_from_input)
+            .reads(&["Input", "StatusEffects"])
+            .writes(&["Velocity"]),
+        SystemDesc::new("velocity_from_target", Stage::Movement, velocity_from_target)
+            .reads(&["Position", "MoveTarget", "Steering", "StatusEffects"])
+            .writes(&["Velocity"])
+            .after("velocity_from_input"),
+        SystemDesc::new("velocity_from_steering", Stage::Movement, velocity_from_steering)
+            .reads(&[
+                "Position",
+                "MoveTarget",
+                "Team",
+                "PathFollower",
+                "StaticCollider",
+                "StatusEffects",
+            ])
+            .writes(&["Steering", "Velocity"])
+            .after("velocity_from_target"),
+        SystemDesc::new("velocity_from_animation", Stage::Movement, velocity_from_animation)
+            .reads(&["CharacterView", "CharacterAnimator", "StatusEffects"])
+            .writes(&["Velocity"])
+            .after("velocity_from_input")
+            .after("velocity_from_target")
+            .after("velocity_from_steering"),
+        SystemDesc::new("velocity_from_knockback", Stage::Movement, velocity_from_knockback)
+            .writes(&["Velocity", "Knockback"])
+            .after("velocity_from_animation"),
+        SystemDesc::new("position", Stage::Movement, position)
+            .reads(&["Velocity"])
+            .writes(&["Position"])
+            .after("velocity_from_knockback"),
+        SystemDesc::new("projectile_range", Stage::Movement, projectile_range)
+            .reads(&["Velocity"])
+            .writes(&["Projectile"])
+            .after("velocity_from_knockback"),
+        SystemDesc::new("collide_static", Stage::Movement, collide_static)
+            .reads(&["StaticCollider", "CharacterView"])
+            .writes(&["Position", "Projectile"])
+            .after("position")
+            .after("projectile_range"),
+        SystemDesc::new("direction_from_input", Stage::Movement, direction_from_input)
+            .reads(&["Input"])
+            .writes(&["Direction"]),
+        SystemDesc::new("direction_from_target", Stage::Movement, direction_from_target)
+            .reads(&["Position", "MoveTarget"])
+            .writes(&["Direction"])
+            .after("collide_static")
+            .after("direction_from_input"),
+        SystemDesc::new("attack_animation", Stage::Animation, attack_animation)
+            .reads(&["Input", "AiInput"])
+            .writes(&["CharacterAnimator", "Stamina"]),
+        SystemDesc::new("combo_attack", Stage::Animation, combo_attack)
+            .reads(&["Input", "AiInput"])
+            .writes(&["Combo", "CharacterAnimator", "Stamina"])
+            .after("attack_animation"),
+        SystemDesc::new("dash_animation", Stage::Animation, dash_animation)
+            .reads(&["Input", "AiInput"])
+            .writes(&["CharacterAnimator", "Stamina"])
+            .after("combo_attack"),
+        SystemDesc::new("damaged_animation", Stage::Animation, damaged_animation)
+            .writes(&["CharacterAnimator"])
+            .after("dash_animation"),
+        SystemDesc::new("stagger_animation", Stage::Animation, stagger_animation)
+            .writes(&["CharacterAnimator"])
+            .after("damaged_animation"),
+        SystemDesc::new("animator", Stage::Animation, animator)
+            .writes(&["CharacterAnimator"])
+            .after("stagger_animation"),
+        SystemDesc::new("finish_animation", Stage::Animation, finish_animation)
+            .writes(&["CharacterAnimator"])
+            .after("animator"),
+        SystemDesc::new("fire_projectiles", Stage::Animation, fire_projectiles)
+            .reads(&["CharacterAnimator", "CharacterView", "Team"])
+            .writes(&["Launcher"])
+            .after("finish_animation"),
+        SystemDesc::new("view_from_animation", Stage::View, view_from_animation)
+            .reads(&["CharacterAnimator"])
+            .writes(&["CharacterView"]),
+        SystemDesc::new("view_from_position", Stage::View, view_from_position)
+            .reads(&["Position", "Direction"])
+            .writes(&["CharacterView"])
+            .after("view_from_animation"),
+        SystemDesc::new("projectile_view", Stage::View, projectile_view)
+            .reads(&["Position"])
+            .writes(&["ProjectileView"]),
+        SystemDesc::new("status_tint", Stage::View, status_tint)
+            .reads(&["StatusEffects"])
+            .writes(&["CharacterView"])
+            .after("view_from_position"),
+        SystemDesc::new("health_bar_length", Stage::View, health_bar_length)
+            .reads(&["Health"])
+            .writes(&["StatusBarView<ForHealth>"]),
+        SystemDesc::new("health_bar_position", Stage::View, health_bar_position)
+            .reads(&["CharacterView"])
+            .writes(&["StatusBarView<ForHealth>"])
+            .after("status_tint")
+            .after("health_bar_length"),
+        SystemDesc::new("stamina_bar_length", Stage::View, stamina_bar_length)
+            .reads(&["Stamina"])
+            .writes(&["StatusBarView<ForStamina>"]),
+        SystemDesc::new("stamina_bar_position", Stage::View, stamina_bar_position)
+            .reads(&["CharacterView", "StatusBarView<ForHealth>"])
+            .writes(&["StatusBarView<ForStamina>"])
+            .after("health_bar_position")
+            .after("stamina_bar_length"),
+        SystemDesc::new("log_events", Stage::View, log_events),
+    ];
+
+    let mut scheduler = Scheduler::default();
+    for system in systems {
+        scheduler.add(system).expect("default systems have unique names");
+    }
+    scheduler
+}
+
+/// Stunned characters get no buttons pressed.
+fn apply_input(world: &mut World, resources: &mut Resources) {
+    let input = &resources.input;
+    let effects = component!(world, StatusEffects);
+    system!(world, |entity_id, _i: &Input| {
+        if effects.get(entity_id).map_or(false, |e| e.is_stunned()) {
+            Input::default()
+        } else {
+            input.clone()
+        }
+    });
+}
+
+fn sword_collider(world: &mut World, _: &mut Resources) {
+    system!(
+        world,
+        |_entity_id,
+         collider: &SwordCollider,
+         view: &CharacterView,
+         animator: &CharacterAnimator| {
+            let mut col = collider.clone();
+            let dir = view.direction + view.weapon_direction;
+            col.line.a = view.position;
+            col.line.b.x = view.position.x + dir.cos() * view.radius * 1.8f32;
+            col.line.b.y = view.position.y + dir.sin() * view.radius * 1.8f32;
+
+            col.active = false;
+            if let Some(id) = animator.playing_id() {
+                if id.is_attack() {
+                    col.active = true;
+                }
+            }
+            col
+        }
+    );
+}
+
+fn body_weapon_collider(world: &mut World, _: &mut Resources) {
+    system!(
+        world,
+        |_entity_id, collider: &BodyWeaponCollider, view: &CharacterView| {
+            let mut col = collider.clone();
+            col.circle.pos = view.position;
+            col.circle.radius = view.radius;
+            col
+        }
+    );
+}
+
+fn projectile_collider(world: &mut World, _: &mut Resources) {
+    system!(
+        world,
+        |_entity_id, collider: &ProjectileCollider, pos: &Position| {
+            let mut col = collider.clone();
+            col.circle.pos = pos.0;
+            col
+        }
+    );
+}
+
+fn index_characters(world: &mut World, resources: &mut Resources) {
+    let grid = &mut resources.spatial.characters;
+    grid.clear();
+    let views = component!(world, CharacterView);
+    component!(world, Position)
+        .iter()
+        .zip_entity(views)
+        .for_each(|(entity_id, pos, view)| grid.insert(entity_id, pos.0.x, pos.0.y, view.radius));
+}
+
+fn index_attacks(world: &mut World, resources: &mut Resources) {
+    let grid = &mut resources.spatial.attacks;
+    grid.clear();
+    component!(world, SwordCollider)
+        .iter()
+        .filter(|(_, sword)| sword.active)
+        .for_each(|(entity_id, sword)| {
+            let center = (sword.line.a + sword.line.b) / 2f32;
+            let radius = sword.line.a.distance(sword.line.b) / 2f32;
+            grid.insert(AttackShape::Sword(entity_id), center.x, center.y, radius);
+        });
+    component!(world, BodyWeaponCollider)
+        .iter()
+        .for_each(|(entity_id, weapon)| {
+            grid.insert(
+                AttackShape::Body(entity_id),
+                weapon.circle.pos.x,
+                weapon.circle.pos.y,
+                weapon.circle.radius,
+            );
+        });
+    component!(world, ProjectileCollider)
+        .iter()
+        .for_each(|(entity_id, projectile)| {
+            grid.insert(
+                AttackShape::Projectile(entity_id),
+                projectile.circle.pos.x,
+                projectile.circle.pos.y,
+                projectile.circle.radius,
+            );
+        });
+}
+
+fn body_defense_collider(world: &mut World, resources: &mut Resources) {
+    let events = &mut resources.events;
+    let attacks = &resources.spatial.attacks;
+    let sword_colliders = component!(world, SwordCollider);
+    let body_weapon_colliders = component!(world, BodyWeaponCollider);
+    let projectile_colliders = component!(world, ProjectileCollider);
+    let projectiles = component!(world, Projectile);
+    let teams = component!(world, Team);
+    let relations = &resources.teams;
+
+    system!(
+        world,
+        |defense_entity_id,
+         body_defense: &BodyDefenseCollider,
+         view: &CharacterView,
+         defense_team: &Team| {
+            let mut new_body_defense = body_defense.clone();
+
+            new_body_defense.circle.pos = view.position;
+            new_body_defense.circle.radius = view.radius;
+
+            attacks
+                .query(view.position.x, view.position.y, view.radius)
+                .into_iter()
+                .for_each(|shape| {
+                    let attacker = shape.entity_id();
+                    if defense_entity_id == attacker {
+                        return;
+                    }
+                    // arrows start inside their shooter, which friendly fire must not hurt
+                    if projectiles.get(attacker).map(|p| p.owner) == Some(defense_entity_id) {
+                        return;
+                    }
+                    let attacker_team = match teams.get(attacker) {
+                        Some(team) => team.team_id(),
+                        None => return,
+                    };
+                    // touching a neutral is not an attack, swinging at it is
+                    let allowed = match shape {
+                        AttackShape::Body(_) => {
+                            relations.is_hostile(attacker_team, defense_team.team_id())
+                        }
+                        _ => relations.can_hurt(attacker_team, defense_team.team_id()),
+                    };
+                    if !allowed {
+                        return;
+                    }
+                    let collided = match shape {
+                        AttackShape::Sword(_) => sword_colliders
+                            .get(attacker)
+                            .map_or(false, |c| c.is_collided(body_defense)),
+                        AttackShape::Body(_) => body_weapon_colliders
+                            .get(attacker)
+                            .map_or(false, |c| c.is_collided(body_defense)),
+                        AttackShape::Projectile(_) => projectile_colliders
+                            .get(attacker)
+                            .map_or(false, |c| c.is_collided(body_defense)),
+                    };
+                    if collided {
+                        events.hit.push(HitEvent {
+                            attacker,
+                            victim: defense_entity_id,
+                            scale: 1f32,
+                        });
+                    }
+                });
+            new_body_defense
+        }
+    );
+}
+
+fn hit_registry(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    system!(world, |_entity_id, registry: &HitRegistry| {
+        let mut new_registry = registry.clone();
+        new_registry.update(delta);
+        new_registry
+    });
+    system!(
+        world,
+        |_entity_id, registry: &HitRegistry, animator: &CharacterAnimator| {
+            let mut new_registry = registry.clone();
+            new_registry.start_attack(animator.play_count());
+            new_registry
+        }
+    );
+}
+
+fn invulnerability_timer(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    system!(world, |_entity_id, invulnerable: &Invulnerable| {
+        let mut new_invulnerable = invulnerable.clone();
+        new_invulnerable.update(delta);
+        new_invulnerable
+    });
+}
+
+/// Drops hits on victims the attack already struck, or who are still invulnerable.
+fn filter_hits(world: &mut World, resources: &mut Resources) {
+    let registries = component!(world, HitRegistry);
+    let invulnerables = component!(world, Invulnerable);
+    let mut accepted = Vec::<(EntityID, EntityID)>::new();
+    resources.events.hit.retain(|hit| {
+        if accepted.contains(&(hit.attacker, hit.victim)) {
+            return false;
+        }
+        if registries
+            .get(hit.attacker)
+            .map_or(false, |r| r.contains(hit.victim))
+        {
+            return false;
+        }
+        if let Some(invulnerable) = invulnerables.get(hit.victim) {
+            // the first hit of the tick triggers invulnerability for the rest
+            if invulnerable.is_active() || accepted.iter().any(|(_, v)| *v == hit.victim) {
+                return false;
+            }
+        }
+        accepted.push((hit.attacker, hit.victim));
+        true
+    });
+
+    let hits = &resources.events.hit;
+    system!(world, |entity_id, registry: &HitRegistry| {
+        let mut new_registry = registry.clone();
+        hits.iter()
+            .filter(|hit| hit.attacker == entity_id)
+            .for_each(|hit| new_registry.register(hit.victim));
+        new_registry
+    });
+}
+
+fn stamina_regen(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    system!(world, |_entity_id, stamina: &Stamina| {
+        let mut new_stamina = stamina.clone();
+        new_stamina.update(delta);
+        new_stamina
+    });
+}
+
+fn raise_shield(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let inputs = component!(world, Input);
+    let ai_inputs = component!(world, AiInput);
+    let animators = component!(world, CharacterAnimator);
+    system!(world, |entity_id, shield: &Shield| {
+        let mut new_shield = shield.clone();
+        let free = animators.get(entity_id).map_or(false, can_act);
+        let input = inputs
+            .get(entity_id)
+            .or_else(|| ai_inputs.get(entity_id).map(|i| &i.0));
+        let raise = free && input.map_or(false, |i| i.defend);
+        new_shield.update(raise, delta);
+        new_shield
+    });
+}
+
+fn shield_collider(world: &mut World, _: &mut Resources) {
+    let shields = component!(world, Shield);
+    system!(
+        world,
+        |entity_id, collider: &ShieldCollider, view: &CharacterView| {
+            let mut col = collider.clone();
+            col.center = view.position;
+            col.radius = view.radius * 1.4f32;
+            col.direction = view.direction;
+            match shields.get(entity_id) {
+                Some(shield) => {
+                    col.half_arc = shield.half_arc;
+                    col.active = shield.raised;
+                }
+                None => col.active = false,
+            }
+            col
+        }
+    );
+}
+
+/// Hits coming from inside a raised shield's arc are parried when the shield just went up,
+/// and blocked otherwise. A block the victim has no stamina left for goes through in full.
+fn block_hits(world: &mut World, resources: &mut Resources) {
+    let shields = component!(world, Shield);
+    let colliders = component!(world, ShieldCollider);
+    let positions = component!(world, Position);
+    let staminas = component!(world, Stamina);
+    let events = &mut resources.events;
+    let mut blocks = Vec::new();
+    let mut spent = HashMap::<EntityID, f32>::new();
+    for hit in events.hit.iter_mut() {
+        let (shield, collider) = match (shields.get(hit.victim), colliders.get(hit.victim)) {
+            (Some(shield), Some(collider)) => (shield, collider),
+            _ => continue,
+        };
+        let covered = positions
+            .get(hit.attacker)
+            .map_or(false, |pos| collider.covers(pos.0));
+        if !covered {
+            continue;
+        }
+        let parried = shield.is_parrying();
+        if !parried {
+            if let Some(stamina) = staminas.get(hit.victim) {
+                let total = spent.entry(hit.victim).or_insert(0f32);
+                if !stamina.can_afford(*total + stamina.block_cost) {
+                    continue;
+                }
+                *total += stamina.block_cost;
+            }
+        }
+        if parried {
+            hit.scale = 0f32;
+        } else {
+            hit.scale *= 1f32 - shield.reduction.min(1f32).max(0f32);
+        }
+        blocks.push(BlockEvent {
+            attacker: hit.attacker,
+            victim: hit.victim,
+            parried,
+        });
+    }
+    let parried = |hit: &HitEvent| {
+        blocks
+            .iter()
+            .any(|b| b.parried && b.attacker == hit.attacker && b.victim == hit.victim)
+    };
+    events.hit.retain(|hit| !parried(hit));
+    blocks.into_iter().for_each(|b| events.block.push(b));
+}
+
+fn block_stamina(world: &mut World, resources: &mut Resources) {
+    let block = &resources.events.block;
+    system!(world, |entity_id, stamina: &Stamina| {
+        let mut new_stamina = stamina.clone();
+        block
+            .iter()
+            .filter(|e| !e.parried && e.victim == entity_id)
+            .for_each(|_| new_stamina.spend(stamina.block_cost));
+        new_stamina
+    });
+}
+
+/// A projectile is used up by the first tick in which it hits anything.
+fn despawn_projectiles(world: &mut World, resources: &mut Resources) {
+    let projectiles = component!(world, Projectile);
+    let commands = &mut resources.commands;
+    resources
+        .events
+        .hit
+        .iter()
+        .filter(|hit| projectiles.get(hit.attacker).is_some())
+        .for_each(|hit| commands.despawn(hit.attacker));
+}
+
+/// Damage from a projectile is credited to the character that shot it, while the push still
+/// points away from the projectile.
+fn resolve_hits(world: &mut World, resources: &mut Resources) {
+    let weapons = component!(world, Weapon);
+    let projectiles = component!(world, Projectile);
+    let combos = component!(world, Combo);
+    let boosts = component!(world, DamageBoost);
+    let armors = component!(world, Armor);
+    let positions = component!(world, Position);
+    let model = &resources.damage_model;
+    let events = &mut resources.events;
+    let default_weapon = Weapon::default();
+    for hit in events.hit.iter() {
+        let weapon = weapons.get(hit.attacker).unwrap_or(&default_weapon);
+        let attacker = projectiles.get(hit.attacker).map_or(hit.attacker, |p| p.owner);
+        let scale = hit.scale
+            * combos.get(hit.attacker).map_or(1f32, |c| c.damage_scale())
+            * boosts.get(attacker).map_or(1f32, |b| b.multiplier);
+        let amount = (model.compute(weapon, armors.get(hit.victim)) as f32 * scale).round() as i32;
+        if amount > 0 {
+            let away = match (positions.get(hit.attacker), positions.get(hit.victim)) {
+                (Some(from), Some(to)) if from.0 != to.0 => (to.0 - from.0).normalize(),
+                _ => Vector::ZERO,
+            };
+            events.damage.push(DamageEvent {
+                attacker,
+                victim: hit.victim,
+                amount,
+                damage_type: weapon.damage_type,
+                impulse: away * weapon.knockback * hit.scale,
+                effects: weapon.effects.clone(),
+                over_time: false,
+            });
+        }
+    }
+}
+
+/// Hands each pickup to the first collector whose body touches it.
+fn collect_pickups(world: &mut World, resources: &mut Resources) {
+    let characters = &resources.spatial.characters;
+    let collectors = component!(world, Collector);
+    let bodies = component!(world, BodyDefenseCollider);
+    let colliders = component!(world, PickupCollider);
+    let commands = &mut resources.commands;
+    let events = &mut resources.events;
+    component!(world, Pickup)
+        .iter()
+        .zip_entity(colliders)
+        .for_each(|(entity_id, pickup, collider)| {
+            let circle = &collider.circle;
+            let collector = characters
+                .query(circle.pos.x, circle.pos.y, circle.radius)
+                .into_iter()
+                .find(|other| {
+                    collectors.get(*other).is_some()
+                        && bodies.get(*other).map_or(false, |b| collider.is_collided(b))
+                });
+            if let Some(collector) = collector {
+                events.pickup.push(PickupEvent {
+                    pickup: entity_id,
+                    collector,
+                    effect: pickup.effect.clone(),
+                });
+                commands.despawn(entity_id);
+            }
+        });
+}
+
+fn apply_pickups(world: &mut World, resources: &mut Resources) {
+    let pickups = &resources.events.pickup;
+    let commands = &mut resources.commands;
+    system!(world, |entity_id, health: &Health| {
+        let mut new_health = health.clone();
+        for event in pickups.iter().filter(|e| e.collector == entity_id) {
+            if let PickupEffect::Heal(amount) = event.effect {
+                new_health.current_health =
+                    std::cmp::min(new_health.current_health + amount, new_health.max_health);
+            }
+        }
+        new_health
+    });
+    system!(world, |entity_id, collector: &Collector| {
+        let mut new_collector = collector.clone();
+        for event in pickups.iter().filter(|e| e.collector == entity_id) {
+            if let PickupEffect::Coins(coins) = event.effect {
+                new_collector.coins += coins;
+            }
+        }
+        new_collector
+    });
+    for event in pickups.iter() {
+        if let PickupEffect::DamageBoost {
+            multiplier,
+            duration,
+        } = event.effect
+        {
+            commands.add_component(
+                event.collector,
+                DamageBoost {
+                    multiplier,
+                    remaining: duration,
+                },
+            );
+        }
+    }
+}
+
+fn damage_boost_timer(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let commands = &mut resources.commands;
+    system!(world, |entity_id, boost: &DamageBoost| {
+        let mut new_boost = boost.clone();
+        new_boost.remaining -= delta;
+        if !new_boost.is_active() {
+            commands.remove_component::<DamageBoost>(entity_id);
+        }
+        new_boost
+    });
+}
+
+/// Counts effects down and turns damage over time into `DamageEvent`s.
+fn status_effects(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let events = &mut resources.events;
+    system!(world, |entity_id, effects: &StatusEffects| {
+        let mut new_effects = effects.clone();
+        for tick in new_effects.update(delta) {
+            if tick.amount > 0 {
+                events.damage.push(DamageEvent {
+                    attacker: tick.source,
+                    victim: entity_id,
+                    amount: tick.amount,
+                    damage_type: tick.damage_type,
+                    impulse: Vector::ZERO,
+                    effects: Vec::new(),
+                    over_time: true,
+                });
+            }
+        }
+        new_effects
+    });
+}
+
+fn hazard_effects(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let characters = &resources.spatial.characters;
+    let positions = component!(world, Position);
+    // (hazard, victim, effects)
+    let mut touched = Vec::<(EntityID, EntityID, Vec<StatusEffect>)>::new();
+    system!(world, |entity_id, hazard: &Hazard| {
+        let mut new_hazard = hazard.clone();
+        new_hazard.timer -= delta;
+        if new_hazard.timer > 0f32 {
+            return new_hazard;
+        }
+        new_hazard.timer += hazard.interval;
+        if let Some(pos) = positions.get(entity_id) {
+            for victim in characters.query(pos.0.x, pos.0.y, hazard.radius) {
+                touched.push((entity_id, victim, hazard.effects.clone()));
+            }
+        }
+        new_hazard
+    });
+    system!(world, |entity_id, effects: &StatusEffects| {
+        let mut new_effects = effects.clone();
+        touched
+            .iter()
+            .filter(|(_, victim, _)| *victim == entity_id)
+            .for_each(|(hazard, _, list)| list.iter().for_each(|e| new_effects.apply(e, *hazard)));
+        new_effects
+    });
+}
+
+fn inflict_effects(world: &mut World, resources: &mut Resources) {
+    let damage = &resources.events.damage;
+    system!(world, |entity_id, effects: &StatusEffects| {
+        let mut new_effects = effects.clone();
+        damage
+            .iter()
+            .filter(|e| e.victim == entity_id)
+            .for_each(|e| e.effects.iter().for_each(|f| new_effects.apply(f, e.attacker)));
+        new_effects
+    });
+}
+
+fn damage(world: &mut World, resources: &mut Resources) {
+    let events = &mut resources.events;
+    system!(world, |entity_id, health: &Health| {
+        let mut new_health = health.clone();
+        let mut killer = None;
+        for event in events.damage.iter().filter(|e| e.victim == entity_id) {
+            if new_health.current_health == 0 {
+                break;
+            }
+            new_health.current_health = std::cmp::max(new_health.current_health - event.amount, 0);
+            if new_health.current_health == 0 {
+                killer = Some(event.attacker);
+            }
+        }
+        if let Some(killer) = killer {
+            events.death.push(DeathEvent {
+                entity: entity_id,
+                killer,
+            });
+        }
+        new_health
+    });
+}
+
+fn apply_knockback(world: &mut World, resources: &mut Resources) {
+    let damage = &resources.events.damage;
+    let masses = component!(world, Mass);
+    let default_mass = Mass::default();
+    system!(world, |entity_id, knockback: &Knockback| {
+        let mut new_knockback = knockback.clone();
+        let mass = masses.get(entity_id).unwrap_or(&default_mass);
+        damage
+            .iter()
+            .filter(|e| e.victim == entity_id)
+            .for_each(|e| new_knockback.push(mass.response(e.impulse)));
+        new_knockback
+    });
+}
+
+fn grant_invulnerability(world: &mut World, resources: &mut Resources) {
+    let damage = &resources.events.damage;
+    system!(world, |entity_id, invulnerable: &Invulnerable| {
+        let mut new_invulnerable = invulnerable.clone();
+        if damage.iter().any(|e| e.victim == entity_id && !e.over_time) {
+            new_invulnerable.trigger();
+        }
+        new_invulnerable
+    });
+}
+
+/// Neutral teams turn hostile towards whoever hurt them.
+fn provoke_neutrals(world: &mut World, resources: &mut Resources) {
+    let teams = component!(world, Team);
+    let relations = &mut resources.teams;
+    for event in resources.events.damage.iter() {
+        let attacker = teams.get(event.attacker).map(|t| t.team_id());
+        let victim = teams.get(event.victim).map(|t| t.team_id());
+        if let (Some(attacker), Some(victim)) = (attacker, victim) {
+            relations.provoke(attacker, victim);
+        }
+    }
+}
+
+fn despawn_dead(_: &mut World, resources: &mut Resources) {
+    let commands = &mut resources.commands;
+    resources
+        .events
+        .death
+        .iter()
+        .for_each(|e| commands.despawn(e.entity));
+}
+
+/// Rolls the `Loot` of everyone who died and scatters the won pickups around the body. The
+/// roll only depends on the tick and the entity, so replays drop the same items.
+fn drop_loot(world: &mut World, resources: &mut Resources) {
+    let loots = component!(world, Loot);
+    let positions = component!(world, Position);
+    let tick = resources.time.tick();
+    let commands = &mut resources.commands;
+    for death in resources.events.death.iter() {
+        let (loot, pos) = match (loots.get(death.entity), positions.get(death.entity)) {
+            (Some(loot), Some(pos)) => (loot, pos.0),
+            _ => continue,
+        };
+        let won: Vec<String> = loot
+            .drops
+            .iter()
+            .enumerate()
+            .filter(|(i, drop)| roll(tick, death.entity, *i) < drop.chance)
+            .map(|(_, drop)| drop.pickup.clone())
+            .collect();
+        let count = won.len();
+        for (i, name) in won.into_iter().enumerate() {
+            let angle = 2f32 * PI * i as f32 / count as f32;
+            let spread = if count > 1 { 12f32 } else { 0f32 };
+            let position = pos + Vector::new(angle.cos(), angle.sin()) * spread;
+            commands.run(move |world, prefabs| {
+                if let Err(e) = prefabs.spawn_pickup(world, &name, position) {
+                    error!("{}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Deterministic number in `[0, 1)`, the same on every platform.
+fn roll(tick: u64, entity_id: EntityID, index: usize) -> f32 {
+    let mut hasher = Fnv::default();
+    (tick, entity_id, index).hash(&mut hasher);
+    (hasher.finish() >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Rebuilds the nav grid whenever the static colliders changed.
+fn build_navigation(world: &mut World, resources: &mut Resources) {
+    let mut hasher = DefaultHasher::new();
+    let mut obstacles = Vec::new();
+    component!(world, StaticCollider)
+        .iter()
+        .for_each(|(_, wall)| {
+            for value in [wall.position.x, wall.position.y, wall.size.x, wall.size.y].iter() {
+                value.to_bits().hash(&mut hasher);
+            }
+            obstacles.push(wall.rect());
+        });
+    obstacles.len().hash(&mut hasher);
+    let signature = hasher.finish();
+    if signature != resources.navigation.signature() {
+        resources.navigation.rebuild(signature, &obstacles);
+    }
+}
+
+/// Picks the hostile each AI character goes after. The current target is kept while it stays
+/// hostile and within the leash, so characters don't flip between two hostiles at about the
+/// same distance; a target removed from the world is forgotten on the next tick.
+fn select_target(world: &mut World, resources: &mut Resources) {
+    let characters = &resources.spatial.characters;
+    let relations = &resources.teams;
+    let teams = component!(world, Team);
+    let positions = component!(world, Position);
+    let healths = component!(world, Health);
+    let priorities = component!(world, TargetPriority);
+    system!(world, |entity_id, targeting: &Targeting| {
+        let mut new_targeting = targeting.clone();
+        let (self_pos, self_team) = match (positions.get(entity_id), teams.get(entity_id)) {
+            (Some(pos), Some(team)) => (pos.0, team.team_id()),
+            _ => {
+                new_targeting.target = None;
+                return new_targeting;
+            }
+        };
+        let is_hostile = |other: EntityID| {
+            other != entity_id
+                && healths.get(other).map_or(false, |h| h.current_health > 0)
+                && teams
+                    .get(other)
+                    .map_or(false, |team| relations.is_hostile(self_team, team.team_id()))
+        };
+        let distance = |other: EntityID| positions.get(other).map(|p| p.0.distance(self_pos));
+
+        let kept = targeting.target.filter(|other| {
+            is_hostile(*other) && distance(*other).map_or(false, |d| d <= targeting.leash())
+        });
+        new_targeting.target = kept.or_else(|| {
+            let priority = |other: EntityID| match targeting.mode {
+                TargetMode::Nearest => 0,
+                TargetMode::Priority => priorities.get(other).map_or(0, |p| p.0),
+            };
+            characters
+                .query(self_pos.x, self_pos.y, targeting.aggro_radius)
+                .into_iter()
+                .filter(|other| is_hostile(*other))
+                .filter_map(|other| distance(other).map(|d| (other, priority(other), d)))
+                .filter(|(_, _, d)| *d <= targeting.aggro_radius)
+                .max_by(|a, b| {
+                    a.1.cmp(&b.1)
+                        .then(b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal))
+                })
+                .map(|(other, _, _)| other)
+        });
+        new_targeting
+    });
+}
+
+/// Walks AI characters toward their `Targeting` target, or holds them in place without one.
+fn chase_target(world: &mut World, _: &mut Resources) {
+    let positions = component!(world, Position);
+    let targetings = component!(world, Targeting);
+    let effects = component!(world, StatusEffects);
+    system!(
+        world,
+        |entity_id, move_target: &MoveTarget, self_pos: &Position| {
+            let mut new_target = move_target.clone();
+            if effects.get(entity_id).map_or(false, |e| e.is_stunned()) {
+                new_target.0 = self_pos.0;
+                return new_target;
+            }
+            let target = targetings
+                .get(entity_id)
+                .and_then(|t| t.target)
+                .and_then(|other| positions.get(other));
+            new_target.0 = match target {
+                Some(pos) => pos.0,
+                None => self_pos.0,
+            };
+            new_target
+        }
+    );
+}
+
+/// Ticks the behavior tree of each character and hands its orders to `MoveTarget`,
+/// `Direction` and `AiInput`. Stunned characters hold still with no button pressed.
+fn behavior_trees(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let library = resources.prefabs.behaviors();
+    let positions = component!(world, Position);
+    let healths = component!(world, Health);
+    let targetings = component!(world, Targeting);
+    let animators = component!(world, CharacterAnimator);
+    let effects = component!(world, StatusEffects);
+    let is_attacking = |entity_id: EntityID| {
+        animators
+            .get(entity_id)
+            .and_then(|a| a.playing_id())
+            .map_or(false, |id| id.is_attack())
+    };
+    let mut outputs = HashMap::<EntityID, BehaviorOutput>::new();
+    system!(world, |entity_id, behavior: &Behavior| {
+        let mut new_behavior = behavior.clone();
+        let (tree, position) = match (library.get(&behavior.tree), positions.get(entity_id)) {
+            (Ok(tree), Some(pos)) => (tree, pos.0),
+            _ => return new_behavior,
+        };
+        if effects.get(entity_id).map_or(false, |e| e.is_stunned()) {
+            let hold = BehaviorOutput {
+                move_target: position,
+                facing: None,
+                input: Input::default(),
+            };
+            outputs.insert(entity_id, hold);
+            return new_behavior;
+        }
+        let target = targetings.get(entity_id).and_then(|t| t.target);
+        let board = &mut new_behavior.blackboard;
+        board.position = position;
+        board.target = target.and_then(|other| positions.get(other)).map(|p| p.0);
+        board.target_attacking = target.map_or(false, |other| is_attacking(other));
+        board.health_ratio = healths.get(entity_id).map_or(1f32, |h| h.ratio());
+        board.can_act = animators.get(entity_id).map_or(false, can_act);
+        outputs.insert(entity_id, tree.run(delta, board));
+        new_behavior
+    });
+
+    system!(world, |entity_id, move_target: &MoveTarget| {
+        let mut new_target = move_target.clone();
+        if let Some(out) = outputs.get(&entity_id) {
+            new_target.0 = out.move_target;
+        }
+        new_target
+    });
+    system!(world, |entity_id, dir: &Direction| {
+        let mut new_dir = dir.clone();
+        if let Some(facing) = outputs.get(&entity_id).and_then(|out| out.facing) {
+            new_dir = facing;
+        }
+        new_dir
+    });
+    system!(world, |entity_id, ai_input: &AiInput| {
+        let mut new_input = ai_input.clone();
+        if let Some(out) = outputs.get(&entity_id) {
+            new_input.0 = out.input.clone();
+        }
+        new_input
+    });
+}
+
+/// Feeds path followers the next waypoint toward the goal the AI systems wrote this tick.
+/// The path is searched again only once the goal moved; in the open the live goal is used
+/// directly, so a chased target is tracked exactly.
+fn follow_path(world: &mut World, resources: &mut Resources) {
+    let navigation = &mut resources.navigation;
+    let positions = component!(world, Position);
+    let move_targets = component!(world, MoveTarget);
+    let mut waypoints = HashMap::<EntityID, Vector>::new();
+    system!(world, |entity_id, follower: &PathFollower| {
+        let mut new_follower = follower.clone();
+        let (position, goal) = match (positions.get(entity_id), move_targets.get(entity_id)) {
+            (Some(pos), Some(target)) => (pos.0, target.0),
+            _ => return new_follower,
+        };
+        let moved = follower
+            .goal
+            .map_or(true, |old| old.distance(goal) > follower.repath_distance);
+        if moved {
+            new_follower.goal = Some(goal);
+            new_follower.waypoints = navigation.find_path(position, goal).unwrap_or_default();
+        }
+        while new_follower.waypoints.len() > 1
+            && new_follower.waypoints[0].distance(position) <= follower.waypoint_radius
+        {
+            new_follower.waypoints.remove(0);
+        }
+        if new_follower.waypoints.len() > 1 {
+            waypoints.insert(entity_id, new_follower.waypoints[0]);
+        }
+        new_follower
+    });
+
+    system!(world, |entity_id, move_target: &MoveTarget| {
+        let mut new_target = move_target.clone();
+        if let Some(waypoint) = waypoints.get(&entity_id) {
+            new_target.0 = *waypoint;
+        }
+        new_target
+    });
+}
+
+/// Walks ranged characters in or out of their shooting band and shoots when the cooldown is
+/// over. While the shot plays they hold still and keep facing the target.
+fn keep_distance(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let positions = component!(world, Position);
+    let targetings = component!(world, Targeting);
+    let animators = component!(world, CharacterAnimator);
+    let staminas = component!(world, Stamina);
+    let effects = component!(world, StatusEffects);
+    // (move target, facing, shoot)
+    let mut decisions = HashMap::<EntityID, (Vector, Option<f32>, bool)>::new();
+    system!(world, |entity_id, keep: &KeepDistance| {
+        let mut new_keep = keep.clone();
+        new_keep.timer = (new_keep.timer - delta).max(0f32);
+        let self_pos = match positions.get(entity_id) {
+            Some(pos) => pos.0,
+            None => return new_keep,
+        };
+        if effects.get(entity_id).map_or(false, |e| e.is_stunned()) {
+            decisions.insert(entity_id, (self_pos, None, false));
+            return new_keep;
+        }
+        let found = targetings
+            .get(entity_id)
+            .and_then(|t| t.target)
+            .and_then(|other| positions.get(other))
+            .map(|p| (p.0, p.0.distance(self_pos)));
+        let (other_pos, distance) = match found {
+            Some(found) => found,
+            None => {
+                decisions.insert(entity_id, (self_pos, None, false));
+                return new_keep;
+            }
+        };
+        let facing = (other_pos.y - self_pos.y).atan2(other_pos.x - self_pos.x);
+        let animator = animators.get(entity_id);
+        let shooting = animator
+            .and_then(|a| a.playing_id())
+            .map_or(false, |id| id.is_attack());
+        let away = if distance > 0f32 {
+            (self_pos - other_pos).normalize()
+        } else {
+            Vector::new(1f32, 0f32)
+        };
+        let target = if shooting {
+            self_pos
+        } else if distance < keep.min_distance {
+            other_pos + away * keep.min_distance
+        } else if distance > keep.max_distance {
+            other_pos + away * keep.max_distance
+        } else {
+            self_pos
+        };
+        let affordable = staminas
+            .get(entity_id)
+            .map_or(true, |stamina| stamina.can_afford(stamina.attack_cost));
+        let shoot = animator.map_or(false, can_act)
+            && affordable
+            && new_keep.timer <= 0f32
+            && distance <= keep.max_distance;
+        if shoot {
+            new_keep.timer = keep.cooldown;
+        }
+        decisions.insert(entity_id, (target, Some(facing), shoot));
+        new_keep
+    });
+
+    system!(world, |entity_id, move_target: &MoveTarget| {
+        let mut new_target = move_target.clone();
+        if let Some((target, _, _)) = decisions.get(&entity_id) {
+            new_target.0 = *target;
+        }
+        new_target
+    });
+    system!(world, |entity_id, dir: &Direction| {
+        let mut new_dir = dir.clone();
+        if let Some((_, Some(facing), _)) = decisions.get(&entity_id) {
+            new_dir = *facing;
+        }
+        new_dir
+    });
+    system!(world, |entity_id, ai_input: &AiInput| {
+        let mut new_input = ai_input.clone();
+        if let Some((_, _, shoot)) = decisions.get(&entity_id) {
+            new_input.0 = Input {
+                attack: *shoot,
+                ..Default::default()
+            };
+        }
+        new_input
+    });
+}
+
+fn enemy_ai(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let positions = component!(world, Position);
+    let targetings = component!(world, Targeting);
+    let healths = component!(world, Health);
+    let animators = component!(world, CharacterAnimator);
+    let effects = component!(world, StatusEffects);
+    let mut outputs = HashMap::<EntityID, AiOutput>::new();
+    system!(world, |entity_id, ai: &EnemyAi| {
+        let mut new_ai = ai.clone();
+        let position = match positions.get(entity_id) {
+            Some(pos) => pos.0,
+            None => return new_ai,
+        };
+        if effects.get(entity_id).map_or(false, |e| e.is_stunned()) {
+            let hold = AiOutput {
+                move_target: position,
+                facing: None,
+                start_attack: false,
+            };
+            outputs.insert(entity_id, hold);
+            return new_ai;
+        }
+        let target = targetings
+            .get(entity_id)
+            .and_then(|t| t.target)
+            .and_then(|other| positions.get(other))
+            .map(|pos| pos.0);
+        let seen = Perception {
+            position,
+            health_ratio: healths.get(entity_id).map_or(1f32, |h| h.ratio()),
+            target,
+            attacking: animators
+                .get(entity_id)
+                .and_then(|a| a.playing_id())
+                .map_or(false, |id| id.is_attack()),
+        };
+        outputs.insert(entity_id, new_ai.update(delta, &seen));
+        new_ai
+    });
+
+    system!(world, |entity_id, move_target: &MoveTarget| {
+        let mut new_target = move_target.clone();
+        if let Some(out) = outputs.get(&entity_id) {
+            new_target.0 = out.move_target;
+        }
+        new_target
+    });
+    system!(world, |entity_id, dir: &Direction| {
+        let mut new_dir = dir.clone();
+        if let Some(facing) = outputs.get(&entity_id).and_then(|out| out.facing) {
+            new_dir = facing;
+        }
+        new_dir
+    });
+    system!(world, |entity_id, ai_input: &AiInput| {
+        let mut new_input = ai_input.clone();
+        if let Some(out) = outputs.get(&entity_id) {
+            new_input.0 = Input {
+                attack: out.start_attack,
+                ..Default::default()
+            };
+        }
+        new_input
+    });
+}
+
+fn velocity_from_input(world: &mut World, _: &mut Resources) {
+    let effects = component!(world, StatusEffects);
+    system!(world, |entity_id, velocity: &Velocity, input: &Input| {
+        let mut new_velocity = velocity.clone();
+        let speed = HERO_SPEED
+            * effects
+                .get(entity_id)
+                .map_or(1f32, |e| e.movement_multiplier());
+        new_velocity.0.x = 0f32;
+        new_velocity.0.y = 0f32;
+        if input.left {
+            new_velocity.0.x = -speed;
+        }
+        if input.right {
+            new_velocity.0.x = speed;
+        }
+        if input.up {
+            new_velocity.0.y = -speed;
+        }
+        if input.down {
+            new_velocity.0.y = speed;
+        }
+        new_velocity
+    });
+}
+
+fn velocity_from_target(world: &mut World, _: &mut Resources) {
+    let effects = component!(world, StatusEffects);
+    let steerings = component!(world, Steering);
+    system!(
+        world,
+        |entity_id, velocity: &Velocity, pos: &Position, target: &MoveTarget| {
+            let mut new_velocity = velocity.clone();
+            if steerings.get(entity_id).is_some() {
+                return new_velocity;
+            }
+            let rate = CHASE_RATE
+                * effects
+                    .get(entity_id)
+                    .map_or(1f32, |e| e.movement_multiplier());
+            let mut tmp = Vector::default();
+            tmp.x = target.0.x - pos.0.x;
+            tmp.y = target.0.y - pos.0.y;
+            new_velocity.0.x = tmp.x * rate;
+            new_velocity.0.y = tmp.y * rate;
+            new_velocity
+        }
+    );
+}
+
+/// Velocity of steered characters: toward their `MoveTarget`, away from crowding allies and
+/// clear of the walls.
+fn velocity_from_steering(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let characters = &resources.spatial.characters;
+    let relations = &resources.teams;
+    let walls: Vec<StaticCollider> = component!(world, StaticCollider)
+        .iter()
+        .map(|(_, wall)| wall.clone())
+        .collect();
+    let positions = component!(world, Position);
+    let targets = component!(world, MoveTarget);
+    let teams = component!(world, Team);
+    let followers = component!(world, PathFollower);
+    let effects = component!(world, StatusEffects);
+    let mut velocities = HashMap::<EntityID, Vector>::new();
+    system!(world, |entity_id, steering: &Steering| {
+        let mut new_steering = steering.clone();
+        let (position, target) = match (positions.get(entity_id), targets.get(entity_id)) {
+            (Some(pos), Some(target)) => (pos.0, target.0),
+            _ => return new_steering,
+        };
+        let team = teams.get(entity_id).map(|t| t.team_id());
+        let is_ally = |other: EntityID| match (team, teams.get(other)) {
+            (Some(a), Some(b)) => relations.relation(a, b.team_id()) == Relation::Allied,
+            _ => false,
+        };
+        let neighbours: Vec<Vector> = characters
+            .query(position.x, position.y, steering.neighbour_radius)
+            .into_iter()
+            .filter(|other| *other != entity_id && is_ally(*other))
+            .filter_map(|other| positions.get(other).map(|p| p.0))
+            .filter(|p| p.distance(position) <= steering.neighbour_radius)
+            .collect();
+        let input = SteeringInput {
+            position,
+            target,
+            passing: followers
+                .get(entity_id)
+                .map_or(false, |f| f.waypoints.len() > 1),
+            neighbours: &neighbours,
+            walls: &walls,
+            speed_multiplier: effects
+                .get(entity_id)
+                .map_or(1f32, |e| e.movement_multiplier()),
+        };
+        velocities.insert(entity_id, new_steering.update(delta, &input));
+        new_steering
+    });
+
+    system!(world, |entity_id, velocity: &Velocity| {
+        let mut new_velocity = velocity.clone();
+        if let Some(steered) = velocities.get(&entity_id) {
+            new_velocity.0 = *steered;
+        }
+        new_velocity
+    });
+}
+
+/// Lunges and dashes authored in the animation, slowed like walking by status effects.
+fn velocity_from_animation(world: &mut World, _: &mut Resources) {
+    let effects = component!(world, StatusEffects);
+    system!(
+        world,
+        |entity_id, vel: &Velocity, view: &CharacterView, animator: &CharacterAnimator| {
+            let mut velocity = vel.clone();
+            if let Some(val) = animator.value() {
+                if val.move_forward != 0f32 {
+                    let speed = val.move_forward
+                        * effects
+                            .get(entity_id)
+                            .map_or(1f32, |e| e.movement_multiplier());
+                    velocity.0.x = view.direction.cos() * speed;
+                    velocity.0.y = view.direction.sin() * speed;
+                }
+            }
+            velocity
+        }
+    );
+}
+
+fn velocity_from_knockback(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let knockbacks = component!(world, Knockback);
+    system!(world, |entity_id, vel: &Velocity| {
+        let mut velocity = vel.clone();
+        if let Some(knockback) = knockbacks.get(entity_id) {
+            velocity.0 += knockback.velocity;
+        }
+        velocity
+    });
+    system!(world, |_entity_id, knockback: &Knockback| {
+        let mut new_knockback = knockback.clone();
+        new_knockback.update(delta);
+        new_knockback
+    });
+}
+
+fn position(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    system!(world, |_entity_id, pos: &Position, vel: &Velocity| {
+        let mut new_pos = pos.clone();
+        new_pos.0.x += vel.0.x * delta;
+        new_pos.0.y += vel.0.y * delta;
+        new_pos
+    });
+}
+
+/// Pushes characters out of walls and stops projectiles that fly into one.
+fn collide_static(world: &mut World, resources: &mut Resources) {
+    let commands = &mut resources.commands;
+    let walls: Vec<StaticCollider> = component!(world, StaticCollider)
+        .iter()
+        .map(|(_, wall)| wall.clone())
+        .collect();
+    if walls.is_empty() {
+        return;
+    }
+    let views = component!(world, CharacterView);
+    system!(world, |entity_id, pos: &Position| {
+        let mut new_pos = pos.clone();
+        if let Some(view) = views.get(entity_id) {
+            let radius = view.radius * view.radius_scale;
+            for wall in walls.iter() {
+                new_pos.0 = wall.push_out(new_pos.0, radius);
+            }
+        }
+        new_pos
+    });
+    let positions = component!(world, Position);
+    system!(world, |entity_id, projectile: &Projectile| {
+        let mut new_projectile = projectile.clone();
+        let hit_wall = positions
+            .get(entity_id)
+            .map_or(false, |pos| walls.iter().any(|wall| wall.contains(pos.0)));
+        if hit_wall && !new_projectile.is_spent() {
+            new_projectile.lifetime = 0f32;
+            commands.despawn(entity_id);
+        }
+        new_projectile
+    });
+}
+
+fn projectile_range(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let commands = &mut resources.commands;
+    system!(world, |entity_id, projectile: &Projectile, vel: &Velocity| {
+        let mut new_projectile = projectile.clone();
+        new_projectile.lifetime -= delta;
+        new_projectile.range -= vel.0.len() * delta;
+        if new_projectile.is_spent() {
+            commands.despawn(entity_id);
+        }
+        new_projectile
+    });
+}
+
+fn direction_from_input(world: &mut World, _: &mut Resources) {
+    system!(world, |_entity_id, dir: &Direction, input: &Input| {
+        let mut new_dir = dir.clone();
+        if input.left {
+            new_dir = PI;
+            if input.up {
+                new_dir = FRAC_PI_4 * 5f32;
+            }
+            if input.down {
+                new_dir = FRAC_PI_4 * 3f32;
+            }
+        } else if input.right {
+            new_dir = 0f32;
+            if input.up {
+                new_dir = FRAC_PI_4 * 7f32;
+            }
+            if input.down {
+                new_dir = FRAC_PI_4;
+            }
+        } else {
+            if input.up {
+                new_dir = FRAC_PI_2 * 3f32;
+            }
+            if input.down {
+                new_dir = FRAC_PI_2;
+            }
+        }
+        new_dir
+    });
+}
+
+fn direction_from_target(world: &mut World, _: &mut Resources) {
+    system!(
+        world,
+        |_entity_id, dir: &Direction, pos: &Position, target: &MoveTarget| {
+            let mut new_dir = dir.clone();
+            if pos.0 != target.0 {
+                new_dir = (target.0.y - pos.0.y).atan2(target.0.x - pos.0.x);
+            }
+            new_dir
+        }
+    );
+}
+
+/// Whether `animator` may be interrupted by an attack, a dash or raising the shield.
+fn can_act(animator: &CharacterAnimator) -> bool {
+    animator.playing_id().map_or(false, |id| {
+        !id.is_attack() && id != CharacterAnimID::Stagger && id != CharacterAnimID::Dash
+    })
+}
+
+/// Starts `action` on characters whose `Input` or `AiInput` asks for it, paying `cost` from
+/// their stamina if they have any. Characters short on stamina are refused.
+fn start_action<P, C>(world: &mut World, action: CharacterAnimID, pressed: P, cost: C)
+where
+    P: Fn(&Input) -> bool,
+    C: Fn(&Stamina) -> f32,
+{
+    let inputs = component!(world, Input);
+    let ai_inputs = component!(world, AiInput);
+    let staminas = component!(world, Stamina);
+    let mut started = Vec::new();
+    system!(world, |entity_id, animator: &CharacterAnimator| {
+        let mut new_animator = animator.clone();
+        let input = inputs
+            .get(entity_id)
+            .or_else(|| ai_inputs.get(entity_id).map(|i| &i.0));
+        let affordable = staminas
+            .get(entity_id)
+            .map_or(true, |stamina| stamina.can_afford(cost(stamina)));
+        if input.map_or(false, |i| pressed(i)) && can_act(&new_animator) && affordable {
+            new_animator.play(action);
+            started.push(entity_id);
+        }
+        new_animator
+    });
+    system!(world, |entity_id, stamina: &Stamina| {
+        let mut new_stamina = stamina.clone();
+        if started.contains(&entity_id) {
+            new_stamina.spend(cost(stamina));
+        }
+        new_stamina
+    });
+}
+
+fn attack_animation(world: &mut World, _: &mut Resources) {
+    start_action(world, CharacterAnimID::Attack, |i| i.attack, |s| s.attack_cost);
+}
+
+/// Pressing attack again inside the cancel window of a combo step cuts it short and starts
+/// the next one. Anything else than an attack resets the chain.
+fn combo_attack(world: &mut World, _: &mut Resources) {
+    let inputs = component!(world, Input);
+    let ai_inputs = component!(world, AiInput);
+    let animators = component!(world, CharacterAnimator);
+    let staminas = component!(world, Stamina);
+    let mut advanced = Vec::<(EntityID, CharacterAnimID)>::new();
+    system!(world, |entity_id, combo: &Combo| {
+        let mut new_combo = combo.clone();
+        let input = inputs
+            .get(entity_id)
+            .or_else(|| ai_inputs.get(entity_id).map(|i| &i.0));
+        let (animator, input) = match (animators.get(entity_id), input) {
+            (Some(animator), Some(input)) => (animator, input),
+            _ => return new_combo,
+        };
+        let attacking = animator.playing_id().map_or(false, |id| id.is_attack());
+        if !attacking {
+            new_combo.current = 0;
+            new_combo.released = false;
+            return new_combo;
+        }
+        if !input.attack {
+            new_combo.released = true;
+            return new_combo;
+        }
+        let next = match combo.next_step() {
+            Some(next) => next.animation,
+            None => return new_combo,
+        };
+        let affordable = staminas
+            .get(entity_id)
+            .map_or(true, |stamina| stamina.can_afford(stamina.attack_cost));
+        if combo.released && combo.in_cancel_window(animator) && affordable {
+            new_combo.current += 1;
+            new_combo.released = false;
+            advanced.push((entity_id, next));
+        }
+        new_combo
+    });
+    let next = |entity_id| advanced.iter().find(|(id, _)| *id == entity_id).map(|a| a.1);
+    system!(world, |entity_id, animator: &CharacterAnimator| {
+        let mut new_animator = animator.clone();
+        if let Some(animation) = next(entity_id) {
+            new_animator.play(animation);
+        }
+        new_animator
+    });
+    system!(world, |entity_id, stamina: &Stamina| {
+        let mut new_stamina = stamina.clone();
+        if next(entity_id).is_some() {
+            new_stamina.spend(stamina.attack_cost);
+        }
+        new_stamina
+    });
+}
+
+fn dash_animation(world: &mut World, _: &mut Resources) {
+    start_action(world, CharacterAnimID::Dash, |i| i.dash, |s| s.dash_cost);
+}
+
+fn damaged_animation(world: &mut World, resources: &mut Resources) {
+    let damage = &resources.events.damage;
+    system!(world, |entity_id, animator: &CharacterAnimator| {
+        let mut new_animator = animator.clone();
+        if let Some(id) = new_animator.playing_id() {
+            let hit = damage
+                .iter()
+                .any(|e| e.victim == entity_id && !e.over_time);
+            if hit && id != CharacterAnimID::Damaged {
+                new_animator.play(CharacterAnimID::Damaged);
+            }
+        }
+        new_animator
+    });
+}
+
+fn stagger_animation(world: &mut World, resources: &mut Resources) {
+    let block = &resources.events.block;
+    system!(world, |entity_id, animator: &CharacterAnimator| {
+        let mut new_animator = animator.clone();
+        if block.iter().any(|e| e.parried && e.attacker == entity_id) {
+            new_animator.play(CharacterAnimID::Stagger);
+        }
+        new_animator
+    });
+}
+
+fn animator(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let finished = &mut resources.events.animation_finished;
+    system!(world, |entity_id, animator: &CharacterAnimator| {
+        let mut new_animator = animator.clone();
+        new_animator.update(delta);
+        if new_animator.is_end() {
+            if let Some(id) = new_animator.playing_id() {
+                finished.push(AnimationFinished {
+                    entity: entity_id,
+                    animation: id,
+                });
+            }
+        }
+        new_animator
+    });
+}
+
+fn finish_animation(world: &mut World, resources: &mut Resources) {
+    let finished = &resources.events.animation_finished;
+    system!(world, |entity_id, animator: &CharacterAnimator| {
+        let mut new_animator = animator.clone();
+        finished
+            .iter()
+            .filter(|e| e.entity == entity_id)
+            .for_each(|e| match e.animation {
+                CharacterAnimID::Attack
+                | CharacterAnimID::Attack2
+                | CharacterAnimID::Finisher
+                | CharacterAnimID::Damaged
+                | CharacterAnimID::Stagger
+                | CharacterAnimID::Dash => {
+                    new_animator.play(CharacterAnimID::Wait);
+                }
+                _ => {}
+            });
+        new_animator
+    });
+}
+
+/// Shoots once per play of an animation, when it reaches a `fire` frame.
+fn fire_projectiles(world: &mut World, resources: &mut Resources) {
+    let commands = &mut resources.commands;
+    let animators = component!(world, CharacterAnimator);
+    let views = component!(world, CharacterView);
+    let teams = component!(world, Team);
+    system!(world, |entity_id, launcher: &Launcher| {
+        let mut new_launcher = launcher.clone();
+        let animator = match animators.get(entity_id) {
+            Some(animator) => animator,
+            None => return new_launcher,
+        };
+        let fire = animator.value().map_or(false, |v| v.fire);
+        if !fire || new_launcher.fired == Some(animator.play_count()) {
+            return new_launcher;
+        }
+        new_launcher.fired = Some(animator.play_count());
+        if let (Some(view), Some(team)) = (views.get(entity_id), teams.get(entity_id)) {
+            let direction = view.direction;
+            let muzzle = view.position
+                + Vector::new(direction.cos(), direction.sin()) * view.radius * view.radius_scale;
+            let team = team.team_id();
+            let projectile = new_launcher.projectile.clone();
+            commands.run(move |world, prefabs| {
+                if let Err(e) =
+                    prefabs.spawn_projectile(world, &projectile, entity_id, team, muzzle, direction)
+                {
+                    error!("{}", e);
+                }
+            });
+        }
+        new_launcher
+    });
+}
+
+fn view_from_animation(world: &mut World, _: &mut Resources) {
+    system!(
+        world,
+        |_entity_id, view: &CharacterView, animator: &CharacterAnimator| {
+            let mut new_view = view.clone();
+            if let Some(val) = animator.value() {
+                new_view.radius_scale = val.radius_scale;
+                new_view.weapon_direction = val.weapon_direction;
+            }
+            new_view
+        }
+    );
+}
+
+fn view_from_position(world: &mut World, _: &mut Resources) {
+    system!(world, |_entity_id, view: &CharacterView, pos: &Position, dir: &Direction| {
+        let mut new_view = view.clone();
+        new_view.position.x = pos.0.x;
+        new_view.position.y = pos.0.y;
+        new_view.direction = *dir;
+        new_view
+    });
+}
+
+fn projectile_view(world: &mut World, _: &mut Resources) {
+    system!(world, |_entity_id, view: &ProjectileView, pos: &Position| {
+        let mut new_view = view.clone();
+        new_view.position = pos.0;
+        new_view
+    });
+}
+
+fn status_tint(world: &mut World, _: &mut Resources) {
+    let effects = component!(world, StatusEffects);
+    system!(world, |entity_id, view: &CharacterView| {
+        let mut new_view = view.clone();
+        new_view.color = match effects.get(entity_id) {
+            Some(effects) => effects.tint(view.base_color),
+            None => view.base_color,
+        };
+        new_view
+    });
+}
+
+fn health_bar_length(world: &mut World, _: &mut Resources) {
+    system!(world, |_entity_id, bar: &StatusBarView<ForHealth>, health: &Health| {
+        let mut new_bar = bar.clone();
+        new_bar.set_ratio(health.ratio());
+        new_bar
+    });
+}
+
+fn health_bar_position(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    system!(
+        world,
+        |_entity_id, bar: &StatusBarView<ForHealth>, view: &CharacterView| {
+            let mut new_bar = bar.clone();
+            new_bar.position = view.position + Vector::new(10f32, -10f32);
+            new_bar.animate(STATUS_BAR_SPEED * delta);
+            new_bar
+        }
+    );
+}
+
+fn stamina_bar_length(world: &mut World, _: &mut Resources) {
+    system!(world, |_entity_id, bar: &StatusBarView<ForStamina>, stamina: &Stamina| {
+        let mut new_bar = bar.clone();
+        new_bar.set_ratio(stamina.ratio());
+        new_bar
+    });
+}
+
+/// Stacks the stamina bar under the health bar, or takes its place when there is none.
+fn stamina_bar_position(world: &mut World, resources: &mut Resources) {
+    let delta = resources.time.delta();
+    let health_bars = component!(world, StatusBarView<ForHealth>);
+    system!(
+        world,
+        |entity_id, bar: &StatusBarView<ForStamina>, view: &CharacterView| {
+            let mut new_bar = bar.clone();
+            new_bar.position = match health_bars.get(entity_id) {
+                Some(health_bar) => health_bar.position + Vector::new(0f32, STATUS_BAR_SPACING),
+                None => view.position + Vector::new(10f32, -10f32),
+            };
+            new_bar.animate(STATUS_BAR_SPEED * delta);
+            new_bar
+        }
+    );
+}
+
+fn log_events(_: &mut World, resources: &mut Resources) {
+    let events = &resources.events;
+    events.damage.iter().for_each(|e| debug!("{:?}", e));
+    events.block.iter().for_each(|e| debug!("{:?}", e));
+    events.death.iter().for_each(|e| info!("{:?}", e));
+    events.pickup.iter().for_each(|e| info!("{:?}", e));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_: &mut World, _: &mut Resources) {}
+
+    fn press_attack(_: &mut World, resources: &mut Resources) {
+        resources.input.attack = true;
+    }
+
+    fn names(scheduler: &mut Scheduler) -> Vec<&'static str> {
+        scheduler
+            .ordered()
+            .unwrap()
+            .iter()
+            .map(|s| s.name())
+            .collect()
+    }
+
+    #[test]
+    fn default_systems_resolve() {
+        assert!(default_scheduler().ordered().is_ok());
+    }
+
+    #[test]
+    fn orders_by_stage_then_constraints_then_insertion() {
+        let mut scheduler = Scheduler::default();
+        scheduler
+            .add(SystemDesc::new("view", Stage::View, noop))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("b", Stage::Combat, noop).after("c"))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("a", Stage::Combat, noop))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("c", Stage::Combat, noop))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("input", Stage::Input, noop).before("view"))
+            .unwrap();
+        assert_eq!(names(&mut scheduler), vec!["input", "a", "c", "b", "view"]);
+
+        scheduler.remove("c").unwrap();
+        match scheduler.ordered() {
+            Err(ScheduleError::UnknownSystem("b", "c")) => {}
+            _ => panic!("expected an unknown system"),
+        }
+    }
+
+    #[test]
+    fn rejects_cycles_and_duplicates() {
+        let mut scheduler = Scheduler::default();
+        scheduler
+            .add(SystemDesc::new("a", Stage::Ai, noop).after("b"))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("b", Stage::Ai, noop).after("a"))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("c", Stage::Ai, noop))
+            .unwrap();
+        match scheduler.ordered() {
+            Err(ScheduleError::Cycle(names)) => assert_eq!(names, vec!["a", "b"]),
+            _ => panic!("expected a cycle"),
+        }
+        match scheduler.add(SystemDesc::new("c", Stage::View, noop)) {
+            Err(ScheduleError::Duplicate("c")) => {}
+            _ => panic!("expected a duplicate"),
+        }
+    }
+
+    #[test]
+    fn rejects_unordered_writers_of_a_stage() {
+        let mut scheduler = Scheduler::default();
+        scheduler
+            .add(SystemDesc::new("a", Stage::Movement, noop).writes(&["Velocity"]))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("b", Stage::Movement, noop).writes(&["Velocity"]))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("c", Stage::View, noop).writes(&["Velocity"]))
+            .unwrap();
+        match scheduler.ordered() {
+            Err(ScheduleError::Conflict("a", "b", "Velocity")) => {}
+            _ => panic!("expected a conflict"),
+        }
+
+        let b = scheduler.remove("b").unwrap();
+        scheduler.add(b.after("a")).unwrap();
+        assert_eq!(names(&mut scheduler), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn rejects_unordered_readers_of_a_written_component() {
+        let mut scheduler = Scheduler::default();
+        scheduler
+            .add(SystemDesc::new("read", Stage::Movement, noop).reads(&["Velocity"]))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("write", Stage::Movement, noop).writes(&["Velocity"]))
+            .unwrap();
+        scheduler
+            .add(SystemDesc::new("other_read", Stage::Movement, noop).reads(&["Velocity"]))
+            .unwrap();
+        match scheduler.ordered() {
+            Err(ScheduleError::Conflict("write", "read", "Velocity")) => {}
+            _ => panic!("expected a conflict"),
+        }
+
+        // readers alone never conflict
+        let write = scheduler.remove("write").unwrap();
+        assert_eq!(names(&mut scheduler), vec!["read", "other_read"]);
+        scheduler
+            .add(write.before("read").before("other_read"))
+            .unwrap();
+        assert_eq!(names(&mut scheduler), vec!["write", "read", "other_read"]);
+    }
+
+    #[test]
+    fn skips_disabled_systems() {
+        let mut scheduler = Scheduler::default();
+        scheduler
+            .add(SystemDesc::new("press_attack", Stage::Input, press_attack))
+            .unwrap();
+        assert!(scheduler.set_enabled("press_attack", false));
+        assert!(!scheduler.is_enabled("press_attack"));
+        assert!(!scheduler.set_enabled("missing", false));
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        scheduler.run(&mut world, &mut resources).unwrap();
+        assert!(!resources.input.attack);
+
+        scheduler.set_enabled("press_attack", true);
+        scheduler.run(&mut world, &mut resources).unwrap();
+        assert!(resources.input.attack);
+    }
+}
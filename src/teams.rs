@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Builtin relationship table, see `assets`.
+const BUILTIN_TEAMS: &str = include_str!("../assets/teams.ron");
+
+#[derive(Debug)]
+pub(crate) enum TeamError {
+    Io(io::Error),
+    Parse(ron::Error),
+}
+
+impl fmt::Display for TeamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TeamError::Io(e) => write!(f, "teams io error: {}", e),
+            TeamError::Parse(e) => write!(f, "teams: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for TeamError {
+    fn from(e: io::Error) -> Self {
+        TeamError::Io(e)
+    }
+}
+
+impl From<ron::Error> for TeamError {
+    fn from(e: ron::Error) -> Self {
+        TeamError::Parse(e)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum Relation {
+    Hostile,
+    /// Left alone until attacked, then hostile for good.
+    Neutral,
+    Allied,
+}
+
+/// How team ids treat each other, as written in `assets/teams.ron`. Pairs missing from the
+/// table are allied when the ids are equal and hostile otherwise.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct TeamRelations {
+    /// Lets attacks hurt allies.
+    #[serde(default)]
+    pub friendly_fire: bool,
+    #[serde(default)]
+    relations: Vec<(u32, u32, Relation)>,
+    /// Neutral pairs turned hostile by an attack. Saved with the rest, so a provoked neutral
+    /// stays hostile across quickloads and replays.
+    #[serde(default)]
+    provoked: BTreeMap<(u32, u32), Relation>,
+}
+
+impl TeamRelations {
+    pub fn builtin() -> Self {
+        Self::load_str(BUILTIN_TEAMS).expect("builtin teams must parse")
+    }
+
+    pub fn load_str(source: &str) -> Result<Self, TeamError> {
+        Ok(ron::de::from_str(source)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TeamError> {
+        Self::load_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Sets the relation both ways.
+    pub fn set(&mut self, a: u32, b: u32, relation: Relation) {
+        self.relations
+            .retain(|(x, y, _)| !((*x == a && *y == b) || (*x == b && *y == a)));
+        self.relations.push((a, b, relation));
+        self.provoked.remove(&key(a, b));
+    }
+
+    pub fn relation(&self, a: u32, b: u32) -> Relation {
+        if let Some(relation) = self.provoked.get(&key(a, b)) {
+            return *relation;
+        }
+        let listed = self
+            .relations
+            .iter()
+            .find(|(x, y, _)| (*x == a && *y == b) || (*x == b && *y == a));
+        match listed {
+            Some((_, _, relation)) => *relation,
+            None if a == b => Relation::Allied,
+            None => Relation::Hostile,
+        }
+    }
+
+    /// Whether AI on team `a` goes after characters of team `b`.
+    pub fn is_hostile(&self, a: u32, b: u32) -> bool {
+        self.relation(a, b) == Relation::Hostile
+    }
+
+    /// Whether a deliberate attack from team `attacker` lands on team `victim`. Neutrals can
+    /// be attacked; allies only with friendly fire on.
+    pub fn can_hurt(&self, attacker: u32, victim: u32) -> bool {
+        match self.relation(attacker, victim) {
+            Relation::Hostile | Relation::Neutral => true,
+            Relation::Allied => self.friendly_fire,
+        }
+    }
+
+    /// Makes a neutral pair hostile after one attacked the other.
+    pub fn provoke(&mut self, attacker: u32, victim: u32) {
+        if attacker != victim && self.relation(attacker, victim) == Relation::Neutral {
+            info!("team {} provoked team {}", attacker, victim);
+            self.provoked.insert(key(attacker, victim), Relation::Hostile);
+        }
+    }
+}
+
+fn key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// Length of one simulation tick in seconds.
+pub(crate) const FIXED_DELTA: f32 = 1f32 / 60f32;
+
+/// Upper bound of ticks run by a single `FixedTimestep::accumulate`, so a long stall does not
+/// make the simulation spiral trying to catch up.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Time {
+    delta: f32,
+    elapsed: f32,
+    tick: u64,
+}
+
+impl Time {
+    pub fn advance(&mut self, delta: f32) {
+        self.delta = delta;
+        self.elapsed += delta;
+        self.tick += 1;
+    }
+    /// Seconds covered by the current tick.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+    /// Seconds since the simulation started.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+    /// Number of ticks run so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(FIXED_DELTA)
+    }
+}
+
+impl FixedTimestep {
+    pub fn new(step: f32) -> Self {
+        Self {
+            step,
+            accumulator: 0f32,
+        }
+    }
+    /// Adds `frame_delta` seconds and returns how many fixed ticks should run now.
+    pub fn accumulate(&mut self, frame_delta: f32) -> u32 {
+        self.accumulator += frame_delta;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+            if steps == MAX_STEPS_PER_FRAME {
+                self.accumulator = 0f32;
+                break;
+            }
+        }
+        steps
+    }
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+    /// How far the accumulator is into the next tick, in `[0, 1)`.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}